@@ -0,0 +1,51 @@
+use crate::audio::audio_format::*;
+use crate::audio::audio_frame::*;
+
+use std::slice::Chunks;
+
+/// A run of interleaved frames, usually the unprocessed rest of an AudioBuffer.
+/// Changes to the samples are written back into the buffer when it is pushed.
+pub struct AudioBlock<'a> {
+    pub data: &'a mut [f32],
+    pub format: &'a AudioFormat,
+    pub time: f64
+}
+
+impl<'a> AudioBlock<'a> {
+    pub fn new(data: &'a mut [f32], format: &'a AudioFormat, time: f64) -> AudioBlock<'a> {
+        AudioBlock {data: data, format: format, time: time}
+    }
+
+    pub fn num_frames(&self) -> usize {
+        if self.format.frame_size == 0 { 0 } else { self.data.len() / self.format.frame_size }
+    }
+
+    /// The time just after the last frame in the block.
+    pub fn end_time(&self) -> f64 {
+        self.time + self.num_frames() as f64 * self.format.frame_duration
+    }
+
+    /// The samples of each frame, read only.
+    pub fn frame_samples(&self) -> Chunks<f32> {
+        self.data.chunks(self.format.frame_size)
+    }
+
+    /// The mean over all channels of each frame, as AudioFrame::mean.
+    pub fn means<'b>(&'b self) -> impl Iterator<Item=f64> + 'b {
+        self.frame_samples().map(|e| e.iter().fold(0f64, |a,b| a+(*b as f64)) / e.len() as f64)
+    }
+
+    /// The absolute mean over all channels of each frame, as AudioFrame::abs_mean.
+    pub fn abs_means<'b>(&'b self) -> impl Iterator<Item=f64> + 'b {
+        self.frame_samples().map(|e| e.iter().fold(0f64, |a,b| a+(b.abs() as f64)) / e.len() as f64)
+    }
+
+    /// Writable frames, for transforms that process the sound.
+    pub fn frames<'b>(&'b mut self) -> impl Iterator<Item=AudioFrame<'b>> {
+        let format = self.format;
+        let time = self.time;
+        self.data.chunks_mut(format.frame_size)
+            .enumerate()
+            .map(move |(i, e)| AudioFrame::new(e, format, time + i as f64 * format.frame_duration))
+    }
+}
@@ -1,24 +1,210 @@
 
 use byteorder::{ByteOrder, ReadBytesExt, WriteBytesExt, BigEndian, LittleEndian};
-use gstreamer;
-use gstreamer_app;
+use byte_slice_cast::*;
 
-use audio::audio_format::*;
-use audio::audio_frame::*;
+use crate::audio::audio_block::*;
+use crate::audio::audio_format::*;
+use crate::audio::audio_frame::*;
+use crate::pipeline::pipeline_utils::PushFailed;
+
+use log::{trace, error};
+use anyhow::Error;
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::audio::audio_iter::*;
+    use std::sync::{Arc, Mutex};
+    use std::sync::mpsc::sync_channel;
+
+    fn new_buffer(samples: Vec<f32>, channels: i32, seconds: u64) -> AudioBuffer {
+        gstreamer::init().unwrap();
+        let mut buffer = gstreamer::Buffer::new();
+        buffer.get_mut().unwrap().set_pts(gstreamer::ClockTime::from_seconds(seconds));
+        AudioBuffer::new(buffer, samples, AudioFormat::new(4, channels))
+    }
+
+    #[test]
+    pub fn test_stereo_frames() {
+        let buffer = new_buffer(vec![0f32, 1f32, 2f32, 3f32, 4f32, 5f32], 2, 0);
+        let mut iter = buffer.into_iter();
+
+        assert_eq!(vec![0f32, 1f32], iter.next().unwrap().data.to_vec());
+        assert_eq!(vec![2f32, 3f32], iter.next().unwrap().data.to_vec());
+        assert_eq!(vec![4f32, 5f32], iter.next().unwrap().data.to_vec());
+        assert!(!iter.has_next());
+        assert!(iter.next().is_none());
+    }
+
+    #[test]
+    pub fn test_surround_frames() {
+        let samples: Vec<f32> = (0..12).map(|e| e as f32).collect();
+        let buffer = new_buffer(samples, 6, 0);
+        assert_eq!(2, buffer.num_frames());
+
+        let mut iter = buffer.into_iter();
+        assert_eq!(vec![0f32, 1f32, 2f32, 3f32, 4f32, 5f32], iter.next().unwrap().data.to_vec());
+        assert_eq!(vec![6f32, 7f32, 8f32, 9f32, 10f32, 11f32], iter.next().unwrap().data.to_vec());
+        assert!(iter.next().is_none());
+    }
+
+    #[test]
+    pub fn test_frame_time() {
+        // 4 frames per second, starting at 1s
+        let buffer = new_buffer(vec![0f32; 8], 2, 1);
+        let mut iter = buffer.into_iter();
+
+        let mut times = Vec::new();
+        while let Some(frame) = iter.next() {
+            times.push(frame.time);
+        }
+        assert_eq!(vec![1f64, 1.25f64, 1.5f64, 1.75f64], times);
+    }
+
+    #[test]
+    pub fn test_partial_frame() {
+        // a trailing sample that doesn't fill a whole frame is not yielded
+        let buffer = new_buffer(vec![0f32, 1f32, 2f32], 2, 0);
+        let mut iter = buffer.into_iter();
+        assert!(iter.next().is_some());
+        assert!(iter.next().is_none());
+    }
+
+    #[test]
+    pub fn test_block() {
+        let buffer = new_buffer(vec![0f32, 1f32, 2f32, 3f32, 4f32, 5f32], 2, 1);
+        let mut iter = buffer.into_iter();
+        iter.next();
+
+        {
+            let mut block = iter.next_block().unwrap();
+            assert_eq!(2, block.num_frames());
+            assert_eq!(1.25f64, block.time);
+            assert_eq!(1.75f64, block.end_time());
+
+            let times: Vec<f64> = block.frames().map(|e| e.time).collect();
+            assert_eq!(vec![1.25f64, 1.5f64], times);
+        }
+
+        assert!(!iter.has_next());
+        assert!(iter.next_block().is_none());
+    }
+
+    #[test]
+    pub fn test_write_samples() {
+        gstreamer::init().unwrap();
+        let format = AudioFormat::new(48000, 2).with_layout(SampleFormat::S16, 0);
+        let data: Vec<u8> = vec![0x00, 0x40, 0x00, 0xc0, 0x00, 0x40, 0x00, 0xc0];
+        let buffer = gstreamer::Buffer::from_mut_slice(data.clone());
+        let samples = AudioBuffer::decode_samples(&data, &format).unwrap();
+
+        let mut iter = AudioBuffer::new(buffer, samples, format).into_iter();
+        iter.next();
+        iter.next().unwrap().scale(0.5f32);
+
+        let mut buffer = iter.into_buffer();
+        buffer.write_samples();
+
+        let map = buffer.buffer.map_readable().unwrap();
+        assert_eq!(&[0x00, 0x40, 0x00, 0xc0, 0x00, 0x20, 0x00, 0xe0], map.as_slice());
+    }
+
+    #[test]
+    pub fn test_trim_before() {
+        let mut buffer = new_buffer((0..8).map(|e| e as f32).collect(), 2, 1);
+        buffer.trim_before(1.5f64);
+
+        assert_eq!(2, buffer.num_frames());
+        assert_eq!(1.5f64, buffer.time);
+        assert_eq!(2f64, buffer.end_time());
+
+        buffer.rebase(1f64);
+        assert_eq!(0.5f64, buffer.time);
+
+        let mut iter = buffer.into_iter();
+        assert_eq!(vec![4f32, 5f32], iter.next().unwrap().data.to_vec());
+    }
+
+    #[test]
+    pub fn test_decode_s16() {
+        let format = AudioFormat::new(48000, 1).with_layout(SampleFormat::S16, 0);
+        let data: Vec<u8> = vec![0x00, 0x40, 0x00, 0xc0];
+        let samples = AudioBuffer::decode_samples(&data, &format).unwrap();
+        assert_eq!(vec![0.5f32, -0.5f32], samples);
+    }
+
+    #[test]
+    pub fn test_iter_skips_buffer_without_frame() {
+        // a buffer shorter than one frame is passed over, not taken as the end of the stream
+        let new_iter = || {
+            let (tx, rx) = sync_channel(2);
+            tx.send(Arc::new(Mutex::new(new_buffer(vec![0f32], 2, 0)))).unwrap();
+            tx.send(Arc::new(Mutex::new(new_buffer(vec![1f32, 2f32, 3f32, 4f32], 2, 1)))).unwrap();
+            AudioIter::new(rx)
+        };
+
+        let mut iter = new_iter();
+        {
+            let block = iter.next_audio_block().unwrap();
+            assert_eq!(2, block.num_frames());
+            assert_eq!(1f64, block.time);
+        }
+        assert!(iter.next_audio_block().is_none());
+
+        let mut iter = new_iter();
+        assert_eq!(vec![1f32, 2f32], iter.next_audio_frame().unwrap().data.to_vec());
+        assert_eq!(vec![3f32, 4f32], iter.next_audio_frame().unwrap().data.to_vec());
+        assert!(iter.next_audio_frame().is_none());
+    }
+}
 
 pub struct AudioBuffer {
     pos: usize,
     frames: usize,
     buffer: gstreamer::Buffer,
-    samples: Vec<i16>,
+    samples: Vec<f32>,
     pub format: AudioFormat,
     pub time: f64
 }
 
 impl AudioBuffer {
-    pub fn new(buffer: gstreamer::Buffer, samples: Vec<i16>, format: AudioFormat) -> AudioBuffer {
+    /// Converts raw interleaved samples into f32 in [-1, 1], so measures keep the full headroom of float sources.
+    pub fn decode_samples(data: &[u8], format: &AudioFormat) -> Option<Vec<f32>> {
+        match format.sample_format {
+            SampleFormat::S16 => data.as_slice_of::<i16>().ok()
+                .map(|samples| samples.iter().map(|e| *e as f32 / 32768f32).collect()),
+            SampleFormat::F32 => data.as_slice_of::<f32>().ok()
+                .map(|samples| samples.to_vec())
+        }
+    }
+
+    /// Converts f32 samples back into the raw format, clipping to [-1, 1] for integer formats.
+    pub fn encode_samples(samples: &[f32], format: &AudioFormat, data: &mut [u8]) -> bool {
+        match format.sample_format {
+            SampleFormat::S16 => match data.as_mut_slice_of::<i16>() {
+                Ok(out) => {
+                    for (o, s) in out.iter_mut().zip(samples.iter()) {
+                        *o = (*s * 32768f32).round().max(-32768f32).min(32767f32) as i16;
+                    }
+                    true
+                },
+                Err(_) => false
+            },
+            SampleFormat::F32 => match data.as_mut_slice_of::<f32>() {
+                Ok(out) => {
+                    for (o, s) in out.iter_mut().zip(samples.iter()) {
+                        *o = *s;
+                    }
+                    true
+                },
+                Err(_) => false
+            }
+        }
+    }
+
+    pub fn new(buffer: gstreamer::Buffer, samples: Vec<f32>, format: AudioFormat) -> AudioBuffer {
         AudioBuffer {
-            time: buffer.get_pts().nanoseconds().unwrap_or(0u64) as f64 / 1_000_000_000f64,
+            time: buffer.pts().map(|e| e.nseconds()).unwrap_or(0u64) as f64 / 1_000_000_000f64,
             pos: 0,
             frames: samples.len() / format.frame_size,
             buffer: buffer, 
@@ -27,8 +213,39 @@ impl AudioBuffer {
         }
     }
 
-    pub fn num_frames(&self, format: &AudioFormat) -> usize {
-        self.samples.len() / (format.channels as usize)
+    pub fn num_frames(&self) -> usize {
+        self.frames
+    }
+
+    /// The time just after the last frame.
+    pub fn end_time(&self) -> f64 {
+        self.time + self.frames as f64 * self.format.frame_duration
+    }
+
+    fn set_time(&mut self, time: f64) {
+        self.time = time;
+        let pts = gstreamer::ClockTime::from_nseconds((time.max(0f64) * 1_000_000_000f64) as u64);
+        self.buffer.make_mut().set_pts(pts);
+    }
+
+    /// Drops the frames before the time.
+    pub fn trim_before(&mut self, time: f64) {
+        let frames = ((time - self.time) / self.format.frame_duration).round();
+        let frames = frames.max(0f64).min(self.frames as f64) as usize;
+        if frames == 0 {
+            return;
+        }
+
+        self.samples.drain(..frames * self.format.frame_size);
+        self.frames -= frames;
+        let time = self.time + frames as f64 * self.format.frame_duration;
+        self.set_time(time);
+    }
+
+    /// Shifts the timestamps so `start` becomes zero.
+    pub fn rebase(&mut self, start: f64) {
+        let time = self.time - start;
+        self.set_time(time);
     }
 
     // pub fn iter<'a>(&'a self) -> Chunks<'a, i32> {
@@ -39,7 +256,32 @@ impl AudioBuffer {
         AudioBufferIter::new(self)
     }
 
-    pub fn into_appsrc<'a>(self, appsrc: &'a mut gstreamer_app::AppSrc) {
+    /// Writes the samples, as changed by transforms, back into the gstreamer buffer.
+    /// The buffer is copied first if anything else still holds a reference to it.
+    pub fn write_samples(&mut self) {
+        let size = self.samples.len() * self.format.sample_format.sample_size();
+        if self.buffer.size() != size {
+            // trimmed buffers are written into a new buffer of the right size
+            let mut buffer = gstreamer::Buffer::with_size(size).unwrap();
+            {
+                let duration = self.frames as f64 * self.format.frame_duration;
+                let buffer_ref = buffer.get_mut().unwrap();
+                buffer_ref.set_pts(self.buffer.pts());
+                buffer_ref.set_duration(gstreamer::ClockTime::from_nseconds((duration * 1_000_000_000f64) as u64));
+            }
+            self.buffer = buffer;
+        }
+
+        let buffer_ref = self.buffer.make_mut();
+        let mut map = buffer_ref.map_writable().unwrap();
+        if !Self::encode_samples(self.samples.as_slice(), &self.format, map.as_mut_slice()) {
+            error!(target: "sink", "Error writing samples into audio buffer");
+        }
+    }
+
+    pub fn into_appsrc<'a>(mut self, appsrc: &'a mut gstreamer_app::AppSrc) -> Result<(), Error> {
+        self.write_samples();
+
         // println!("Writing audio buffer with time {:?} / duration {:?}", self.clock_time, self.duration);
         // let i32_size = ::std::mem::size_of::<i32>();
         // let buf_size = self.buffer.len() * i32_size;
@@ -68,10 +310,9 @@ impl AudioBuffer {
         //     // }
         // }
         // println!("Pushing audio buffer into appsink");
-        println!("OUT {}", self.buffer.get_pts());
-        if appsrc.push_buffer(self.buffer) != gstreamer::FlowReturn::Ok {
-            println!("Error writing Audio Buffer")
-        }
+        trace!(target: "sink", "OUT {:?}", self.buffer.pts());
+        appsrc.push_buffer(self.buffer).map_err(|e| PushFailed("audio", e))?;
+        Ok(())
     }
 }
 
@@ -95,25 +336,45 @@ impl<'i> AudioBufferIter {
     }
 
     pub fn has_next(&self) -> bool {
-        self.pos + self.window <= self.buffer.samples.len()
+        self.window > 0 && self.pos + self.window <= self.buffer.samples.len()
     }
 
+    /// Yields one frame per step: a sample for each channel, interleaved.
     pub fn next<'b>(&'b mut self) -> Option<AudioFrame<'b>> {
-        if self.pos + self.window <= self.buffer.samples.len() {
-            let time = self.buffer.time + self.pos as f64 * self.buffer.format.frame_duration;
+        if self.has_next() {
+            let index = self.pos / self.window;
+            let time = self.buffer.time + index as f64 * self.buffer.format.frame_duration;
 
             let start = self.pos;
             let end = self.pos+self.window;
 
-            let slice = &self.buffer.samples[start..end];
+            let slice = &mut self.buffer.samples[start..end];
             let frame = AudioFrame::new(slice, &self.buffer.format, time);
 
-            self.pos += 1;
+            self.pos += self.window;
 
             Some(frame)
         } else { None }
     }
 
+    /// Yields all of the remaining frames at once.
+    pub fn next_block<'b>(&'b mut self) -> Option<AudioBlock<'b>> {
+        if self.has_next() {
+            let index = self.pos / self.window;
+            let time = self.buffer.time + index as f64 * self.buffer.format.frame_duration;
+
+            let start = self.pos;
+            let end = self.buffer.samples.len() - (self.buffer.samples.len() - start) % self.window;
+
+            let slice = &mut self.buffer.samples[start..end];
+            let block = AudioBlock::new(slice, &self.buffer.format, time);
+
+            self.pos = end;
+
+            Some(block)
+        } else { None }
+    }
+
     pub fn into_buffer(self) -> AudioBuffer {
         self.buffer
     }
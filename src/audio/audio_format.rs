@@ -1,7 +1,45 @@
-#[derive(Copy, Clone, Debug)]
+
+/// How samples are stored in the raw audio buffers.
+/// Whatever the source format, frames hand samples to transforms as f32 in [-1, 1].
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum SampleFormat {
+    S16,
+    F32
+}
+
+impl SampleFormat {
+    pub fn from_caps_name(name: &str) -> Option<SampleFormat> {
+        if name == gstreamer_audio::AUDIO_FORMAT_S16.to_string() {
+            Some(SampleFormat::S16)
+        } else if name == gstreamer_audio::AUDIO_FORMAT_F32.to_string() {
+            Some(SampleFormat::F32)
+        } else {
+            None
+        }
+    }
+
+    pub fn caps_name(&self) -> String {
+        match self {
+            &SampleFormat::S16 => gstreamer_audio::AUDIO_FORMAT_S16.to_string(),
+            &SampleFormat::F32 => gstreamer_audio::AUDIO_FORMAT_F32.to_string()
+        }
+    }
+
+    pub fn sample_size(&self) -> usize {
+        match self {
+            &SampleFormat::S16 => 2,
+            &SampleFormat::F32 => 4
+        }
+    }
+}
+
+#[derive(Copy, Clone, Debug, PartialEq)]
 pub struct AudioFormat {
     pub rate: i32,
     pub channels: i32,
+    pub sample_format: SampleFormat,
+    /// gstreamer channel-mask of the layout, or 0 if the caps didn't carry one (mono/stereo)
+    pub channel_mask: u64,
     pub frame_size: usize,
     pub frame_duration: f64
 }
@@ -11,6 +49,8 @@ impl AudioFormat {
         AudioFormat {
             rate: 0,
             channels: 0,
+            sample_format: SampleFormat::S16,
+            channel_mask: 0,
             frame_size: 0,
             frame_duration: 0f64
         }
@@ -20,11 +60,31 @@ impl AudioFormat {
         AudioFormat {
             rate: rate,
             channels: channels,
+            sample_format: SampleFormat::S16,
+            channel_mask: 0,
             frame_size: channels as usize,
-            frame_duration: 1f64 / ((rate as f64) * (channels as f64))
+            frame_duration: 1f64 / (rate as f64)
         }
     }
 
+    pub fn with_layout(self, sample_format: SampleFormat, channel_mask: u64) -> AudioFormat {
+        AudioFormat {
+            sample_format: sample_format,
+            channel_mask: channel_mask,
+            ..self
+        }
+    }
+
+    /// Reads the sample format, rate and channel layout from negotiated raw audio caps.
+    pub fn from_caps(caps: &gstreamer::CapsRef) -> Option<AudioFormat> {
+        let structure = caps.structure(0)?;
+        let sample_format = SampleFormat::from_caps_name(structure.get::<&str>("format").ok()?)?;
+        let rate = structure.get::<i32>("rate").ok()?;
+        let channels = structure.get::<i32>("channels").ok()?;
+        let channel_mask = structure.get::<gstreamer::Bitmask>("channel-mask").map(|e| e.0).unwrap_or(0);
+        Some(AudioFormat::new(rate, channels).with_layout(sample_format, channel_mask))
+    }
+
     pub fn frames_in(&self, time: f64) -> usize {
         (time * (self.rate as f64)).ceil() as usize
     }
@@ -1,13 +1,20 @@
-use audio::audio_format::*;
+use crate::audio::audio_format::*;
+
+/// True when every sample is zero.  Measures skip silent frames the same way whether they
+/// are given one frame at a time or a whole block, so padding doesn't drag their averages down.
+pub fn is_silent(samples: &[f32]) -> bool {
+    samples.iter().all(|e| *e == 0f32)
+}
 
 pub struct AudioFrame<'a> {
-    pub data: &'a [i16],
+    /// Interleaved samples, one per channel.  Changes are written back into the buffer when it is pushed.
+    pub data: &'a mut [f32],
     pub format: &'a AudioFormat,
     pub time: f64
 }
 
 impl<'a> AudioFrame<'a> {
-    pub fn new(data: &'a [i16], format: &'a AudioFormat, time: f64) -> AudioFrame<'a> {
+    pub fn new(data: &'a mut [f32], format: &'a AudioFormat, time: f64) -> AudioFrame<'a> {
         AudioFrame {data: data, format:format, time: time}
     }
 
@@ -18,4 +25,30 @@ impl<'a> AudioFrame<'a> {
     pub fn abs_sum(&self) -> f64 {
         self.data.iter().map(|e| e.abs() as f64).fold(0f64, |a,b| a+b)
     }
+
+    /// Mean over all channels, i.e. a mono downmix of the frame.
+    /// Measures use the means so they read the same for mono, stereo or 5.1 sources.
+    pub fn mean(&self) -> f64 {
+        if self.data.len() == 0 { 0f64 } else { self.sum() / self.data.len() as f64 }
+    }
+
+    pub fn abs_mean(&self) -> f64 {
+        if self.data.len() == 0 { 0f64 } else { self.abs_sum() / self.data.len() as f64 }
+    }
+
+    pub fn is_silent(&self) -> bool {
+        is_silent(self.data)
+    }
+
+    /// The sample of a single channel, in the order given by the format's channel layout.
+    pub fn channel(&self, channel: usize) -> f32 {
+        self.data[channel]
+    }
+
+    /// Multiplies every channel by the gain.
+    pub fn scale(&mut self, gain: f32) {
+        for sample in self.data.iter_mut() {
+            *sample *= gain;
+        }
+    }
 }
\ No newline at end of file
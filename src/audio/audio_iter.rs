@@ -3,12 +3,13 @@ use std::sync::Mutex;
 use std::sync::Arc;
 use std::sync::mpsc::Receiver;
 
-use audio::audio_buffer::*;
-use audio::audio_format::*;
-use audio::audio_frame::*;
+use crate::audio::audio_block::*;
+use crate::audio::audio_buffer::*;
+use crate::audio::audio_format::*;
+use crate::audio::audio_frame::*;
 
 pub struct AudioIter {
-    audio_channel: Box<Iterator<Item=AudioBuffer>>,
+    audio_channel: Box<dyn Iterator<Item=AudioBuffer>>,
     audio_frame_iterator: Option<AudioBufferIter>,
     finished_buffers: LinkedList<AudioBuffer>
 }
@@ -16,15 +17,12 @@ pub struct AudioIter {
 impl AudioIter {
     pub fn new(audio_channel: Receiver<Arc<Mutex<AudioBuffer>>>) -> AudioIter {
         let audio_iter = audio_channel.into_iter().map(|e| Mutex::into_inner(Arc::try_unwrap(e).ok().unwrap()).ok().unwrap());
-        let mut processor = AudioIter {
+        // nothing is received until a frame or the format is asked for, as in VideoIter::from_buffers
+        AudioIter {
             audio_frame_iterator: None,
             audio_channel: Box::new(audio_iter),
             finished_buffers: LinkedList::new()
-        };
-
-        processor.next_audio_buffer();
-
-        processor
+        }
     }
 
     pub fn format(&mut self) -> Option<AudioFormat> {
@@ -63,12 +61,41 @@ impl AudioIter {
         }
     }
 
+    fn next_audio_block_in_buffer(&mut self) -> Option<AudioBlock> {
+       if let Some(ref mut iter) = self.audio_frame_iterator {
+            iter.next_block()
+        } else {
+            None
+        }
+    }
+
+    /// Moves on to the next buffer with a whole frame left in it.
+    /// Buffers without one are passed through unprocessed, so only the end of the channel ends the stream.
+    fn skip_to_next_frame(&mut self) -> bool {
+        while !self.audio_frame_iterator.as_ref().map(|iter| iter.has_next()).unwrap_or(false) {
+            self.next_audio_buffer();
+            if self.audio_frame_iterator.is_none() {
+                return false;
+            }
+        }
+
+        true
+    }
+
+    /// The unprocessed rest of the current buffer, or the whole next buffer.
+    pub fn next_audio_block(&mut self) -> Option<AudioBlock> {
+        if !self.skip_to_next_frame() {
+            return None;
+        }
+
+        self.next_audio_block_in_buffer()
+    }
+
     pub fn next_audio_frame(&mut self) -> Option<AudioFrame> {
-        if self.audio_frame_iterator.is_some() && self.audio_frame_iterator.as_ref().unwrap().has_next() {
-            return self.next_audio_frame_in_buffer();
+        if !self.skip_to_next_frame() {
+            return None;
         }
 
-        self.next_audio_buffer();
         self.next_audio_frame_in_buffer()
     }
 }
\ No newline at end of file
@@ -1,3 +1,4 @@
+pub mod audio_block;
 pub mod audio_buffer;
 pub mod audio_format;
 pub mod audio_frame;
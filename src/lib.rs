@@ -0,0 +1,25 @@
+//! Audio reactive video transforms on GStreamer.
+//!
+//! A `FrameSource` decodes the input into audio and video buffers, a `FrameTransform` is run over
+//! their frames, and a `FrameSink` encodes the result.  Measures turn frames into the values
+//! transforms react to.
+
+pub mod audio;
+pub mod pipeline;
+pub mod video;
+pub mod measures;
+
+pub use crate::audio::audio_block::AudioBlock;
+pub use crate::audio::audio_buffer::AudioBuffer;
+pub use crate::audio::audio_format::{AudioFormat, SampleFormat};
+pub use crate::audio::audio_frame::AudioFrame;
+pub use crate::video::video_buffer::VideoBuffer;
+pub use crate::video::video_format::VideoFormat;
+pub use crate::video::video_frame::VideoFrame;
+
+pub use crate::pipeline::frame_transform::FrameTransform;
+pub use crate::pipeline::frame_source::{FrameSource, SourceInput, SourceStreams, TimeRange};
+pub use crate::pipeline::frame_sink::{FrameSink, SinkType};
+pub use crate::pipeline::transform_params::TransformParams;
+pub use crate::pipeline::transform_registry::TransformRegistry;
+pub use crate::measures::{Measure, StatefulMeasure};
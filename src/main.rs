@@ -1,92 +1,332 @@
-#![feature(conservative_impl_trait)]
-
-
-mod audio;
-mod pipeline;
-mod video;
-mod measures;
 mod osx;
 
 use std::thread;
 
-use pipeline::pipeline_utils::*;
-use pipeline::frame_source::*;
-use pipeline::frame_sink::*;
-
-use pipeline::frame_transform::*;
-use osx::*;
+use recode::pipeline::cancel::*;
+use recode::pipeline::encoder_settings::*;
+use recode::pipeline::pipeline_utils::*;
+use recode::pipeline::progress::*;
+use recode::pipeline::frame_source::*;
+use recode::pipeline::frame_sink::*;
+use recode::pipeline::frame_still::*;
+use recode::pipeline::frame_trace::*;
+use recode::pipeline::output_format::*;
+
+use recode::pipeline::basic_transforms::*;
+use recode::pipeline::frame_transform::*;
+use recode::video::video_format::*;
+use recode::pipeline::transform_params::*;
+use recode::pipeline::transform_registry::*;
+use crate::osx::*;
 
 /////////////
 
-extern crate byteorder;
-
-#[macro_use]
-extern crate gstreamer;
-extern crate gstreamer_app;
-extern crate gstreamer_audio;
-extern crate gstreamer_video;
-extern crate byte_slice_cast;
-extern crate rustfft;
-extern crate apodize;
-extern crate num_complex;
-extern crate stats;
-extern crate glib;
-extern crate rayon;
-extern crate cpuprofiler;
-
-#[macro_use]
-extern crate serde_derive;
-extern crate docopt;
-
 use docopt::Docopt;
 
-use gstreamer::prelude::*;
+use log::{debug, error, warn, LevelFilter};
+use serde::Deserialize;
+
+use gstreamer::glib;
 
 use std::env;
-#[cfg(feature = "v1_10")]
-use std::sync::{RwLock, Mutex};
+use std::fs::File;
+use std::io;
+use std::io::{BufWriter, Write};
+use std::panic;
+use std::process;
+
+use anyhow::Error;
+
+#[derive(Debug, thiserror::Error)]
+#[error("Invalid canvas {}, expected WIDTHxHEIGHT@FPS", .0)]
+struct InvalidCanvas(String);
+
+#[derive(Debug, thiserror::Error)]
+#[error("Invalid time {}, expected seconds or [HH:]MM:SS[.sss]", .0)]
+struct InvalidTime(String);
+
+/// Sets up logging on stderr, at info level by default.  -v enables debug, -vv trace and
+/// -q only shows warnings and errors.  RECODE_LOG takes env_logger filters, e.g. measures=trace.
+fn init_logging(verbosity: usize, quiet: bool) {
+    let level = if quiet {
+        LevelFilter::Warn
+    } else {
+        match verbosity {
+            0 => LevelFilter::Info,
+            1 => LevelFilter::Debug,
+            _ => LevelFilter::Trace
+        }
+    };
 
-extern crate failure;
-use failure::Error;
+    let mut builder = env_logger::Builder::new();
+    builder.filter(None, level);
+    if let Ok(spec) = env::var("RECODE_LOG") {
+        builder.parse_filters(&spec);
+    }
+    builder.init();
+}
 
-#[macro_use]
-extern crate failure_derive;
+/// Parses a time in seconds, such as 75.5, 1:15.5 or 0:01:15.5.
+fn parse_time(time: &str) -> Result<f64, Error> {
+    let mut seconds = 0f64;
+    for part in time.split(':') {
+        let value = part.parse::<f64>().map_err(|_| InvalidTime(time.to_string()))?;
+        if value < 0f64 {
+            Err(InvalidTime(time.to_string()))?;
+        }
+        seconds = seconds * 60f64 + value;
+    }
+    Ok(seconds)
+}
+
+/// The x264 two-pass stats file, removed when dropped so a failed or cancelled encode
+/// doesn't leave it behind.
+struct StatsFiles(Option<String>);
+
+impl Drop for StatsFiles {
+    fn drop(&mut self) {
+        if let Some(ref stats_file) = self.0 {
+            ::std::fs::remove_file(stats_file).ok();
+            ::std::fs::remove_file(format!("{}.mbtree", stats_file)).ok();
+        }
+    }
+}
 
 const USAGE: &'static str = "
 Recode.
 
 Usage:
-  recode convert <input-mp4> <output-mp4>
-  recode preview <input-mp4>
-  recode trace <input-mp4> <measure>
+  recode convert <input-mp4> <output-mp4> [--gst] [--audio=<file> [--mix-audio]] [--config=<file>] [--transform=<name>] [--canvas=<size>] [--start=<time>] [--end=<time>] [--preroll=<time>] [--progress=<mode>] [--rate-control=<mode>] [--bitrate=<kbps>] [--quantizer=<q>] [--preset=<name>] [--tune=<name>] [--profile=<name>] [--keyint=<frames>] [--two-pass | --lossless=<fmt>] [--container=<name>] [--video-codec=<name>] [--audio-codec=<name>] [-v... | -q]
+  recode frames <input-mp4> <pattern> [--gst] [--audio=<file> [--mix-audio]] [--config=<file>] [--transform=<name>] [--canvas=<size>] [--progress=<mode>] [-v... | -q]
+  recode preview <input-mp4> [--gst] [--audio=<file> [--mix-audio]] [--config=<file>] [--transform=<name>] [--canvas=<size>] [--start=<time>] [--end=<time>] [--preroll=<time>] [--progress=<mode>] [-v... | -q]
+  recode still <input-mp4> --at=<time> <output-png> [--gst] [--audio=<file> [--mix-audio]] [--config=<file>] [--transform=<name>] [--preroll=<time>] [-v... | -q]
+  recode trace <input-mp4> <measure> [--gst] [--format=<fmt>] [--output=<file>] [-v... | -q]
+  recode --list-transforms
   recode (-h | --help)
   recode --version
 
+Frames writes one image per frame to <pattern>, e.g. frames/%05d.png or frames/%05d.jpg,
+and the audio to a WAV file named after the pattern, e.g. frames/audio.wav.
+
+Still renders the single frame showing at --at to a PNG or JPEG, after running the audio
+measures over the --preroll before it.
+
+Measures:
+  edge                  Normalized audio edge (NormalizedAudioEdgeMeasure).
+  volume                Normalized audio volume (NormalizedAudioVolumeMeasure).
+  fft                   Smoothed audio spectrum (FFTMeasure), one column per bin.
+  theta_r               Average hue angle of the video.
+  r                     Average hue spread of the video.
+
 Options:
-  -h --help     Show this screen.
-  --version     Show version.
+  -h --help             Show this screen.
+  --version             Show version.
+  --gst                 Treat <input-mp4> as a gst-launch style source description,
+                        e.g. \"videotestsrc num-buffers=300 ! video/x-raw,width=1280,height=720 audiotestsrc\".
+                        Without it, <input-mp4> is a file path or a URI such as file:///clip.mp4.
+  --audio=<file>        Drive the visuals with this audio file (or URI), and output it
+                        in place of the input's audio track.
+  --mix-audio           With --audio, mix the input's audio track with the audio file instead.
+  --config=<file>       Look parameters for the transform, as TOML or JSON.
+                        Encoder settings can be given in an [encoder] table.
+  --transform=<name>    Transform to render with.  Join names with + to chain them [default: hue].
+  --list-transforms     List the available transforms.
+  --canvas=<size>       For inputs without video, paint the transform on a blank canvas
+                        of this size and rate, e.g. 1920x1080@30.
+  --container=<name>    Output container, mp4, mkv or webm.  Needed unless <output-mp4>
+                        has one of them as its extension.
+  --video-codec=<name>  h264, vp8 or vp9.  Defaults to h264, or vp9 for webm.
+  --audio-codec=<name>  aac, opus, vorbis or flac.  Defaults to aac for mp4, flac for mkv
+                        and opus for webm.
+  --rate-control=<mode>  Encoder rate control: abr, cbr, crf or quantizer.  Defaults to abr.
+  --bitrate=<kbps>      Bitrate for abr and cbr.  Defaults to a bitrate scaled by the output
+                        size and frame rate.
+  --quantizer=<q>       Rate factor for crf, or quantizer for quantizer (0-50).  Defaults to 21.
+  --preset=<name>       x264 speed preset, e.g. veryfast, medium or slow.
+  --tune=<name>         x264 tuning, e.g. film, animation or fastdecode+zerolatency.
+  --profile=<name>      h264 profile, baseline, main or high.  Defaults to high.
+  --keyint=<frames>     Maximum keyframe interval.  Defaults to 2 seconds of frames.
+  --two-pass            Run the whole conversion twice, first to gather encoder stats, then to encode
+                        using them.  Needs abr rate control.
+  --lossless=<fmt>      Write a lossless file for further editing, ignoring the codec and encoder flags:
+                        ffv1 (FFV1 and FLAC in MKV), h264 (lossless 4:4:4 x264 and FLAC in MKV)
+                        or y4m (Y4M video, with the audio in a WAV file next to it).
+  --start=<time>        Only output from this time on, in seconds or [HH:]MM:SS[.sss].
+  --end=<time>          Only output until this time.
+  --progress=<mode>     Progress on stderr: human, json (one object per line) or quiet [default: human].
+  --at=<time>           Time of the still.
+  --preroll=<time>      Audio analysed before --start or the still, to warm up the measures [default: 10].
+  --format=<fmt>        Trace output format, csv or json [default: csv].
+  --output=<file>       Trace output file, - for stdout [default: -].
+  -v                    More log output on stderr, -vv for per-frame telemetry.
+  -q                    Only log warnings and errors.
+
+Logging can be filtered per target (source, sink, transform, measures, pipeline) with
+RECODE_LOG, e.g. RECODE_LOG=measures=trace.
 ";
 
 #[derive(Debug, Deserialize)]
 struct Args {
     arg_input_mp4: String,
     arg_output_mp4: String,
+    arg_pattern: String,
+    arg_output_png: String,
     arg_measure: String,
+    flag_gst: bool,
+    flag_audio: Option<String>,
+    flag_mix_audio: bool,
+    flag_config: Option<String>,
+    flag_transform: String,
+    flag_list_transforms: bool,
+    flag_canvas: Option<String>,
+    flag_rate_control: Option<String>,
+    flag_bitrate: Option<u32>,
+    flag_quantizer: Option<u32>,
+    flag_preset: Option<String>,
+    flag_tune: Option<String>,
+    flag_profile: Option<String>,
+    flag_keyint: Option<u32>,
+    flag_two_pass: bool,
+    flag_lossless: Option<String>,
+    flag_container: Option<String>,
+    flag_video_codec: Option<String>,
+    flag_audio_codec: Option<String>,
+    flag_format: String,
+    flag_output: String,
     cmd_convert: bool,
+    cmd_frames: bool,
     cmd_preview: bool,
-    cmd_trace: bool
+    cmd_still: bool,
+    cmd_trace: bool,
+    flag_at: String,
+    flag_progress: String,
+    flag_start: Option<String>,
+    flag_end: Option<String>,
+    flag_preroll: String,
+    flag_v: usize,
+    flag_q: bool
 }
 
 impl Args {
-    fn get_sinktype(&self) -> Option<SinkType> {
-        if self.cmd_preview {
-            Some(SinkType::playback)
-        } else if self.cmd_convert {
-            Some(SinkType::file_mp4(self.arg_output_mp4.clone()))
+    fn get_source_input(&self) -> SourceInput {
+        if self.flag_gst {
+            SourceInput::Description(self.arg_input_mp4.clone())
         } else {
-            None
+            SourceInput::from_arg(&self.arg_input_mp4)
         }
     }
+
+    fn get_audio_input(&self) -> Option<SourceInput> {
+        self.flag_audio.as_ref().map(|e| SourceInput::from_arg(e))
+    }
+
+    /// The [encoder] table of the config file, overridden by the encoder flags.
+    fn get_encoder_settings(&self) -> Result<EncoderSettings, Error> {
+        let mut settings = match self.flag_config {
+            Some(ref path) => EncoderSettings::load(path)?,
+            None => EncoderSettings::default()
+        };
+
+        if let Some(ref rate_control) = self.flag_rate_control {
+            settings.rate_control = Some(RateControl::from_name(rate_control)?);
+        }
+        if self.flag_bitrate.is_some() {
+            settings.bitrate = self.flag_bitrate;
+        }
+        if self.flag_quantizer.is_some() {
+            settings.quantizer = self.flag_quantizer;
+        }
+        if self.flag_preset.is_some() {
+            settings.speed_preset = self.flag_preset.clone();
+        }
+        if self.flag_tune.is_some() {
+            settings.tune = self.flag_tune.clone();
+        }
+        if self.flag_profile.is_some() {
+            settings.profile = self.flag_profile.clone();
+        }
+        if self.flag_keyint.is_some() {
+            settings.keyframe_interval = self.flag_keyint;
+        }
+
+        settings.validate()?;
+        Ok(settings)
+    }
+
+    fn get_range(&self) -> Result<TimeRange, Error> {
+        let start = match self.flag_start {
+            Some(ref time) => parse_time(time)?,
+            None => 0f64
+        };
+        let end = match self.flag_end {
+            Some(ref time) => Some(parse_time(time)?),
+            None => None
+        };
+
+        Ok(TimeRange {
+            start: start,
+            end: end
+        })
+    }
+
+    fn get_stats_file(&self) -> String {
+        format!("{}.pass-stats", self.arg_output_mp4)
+    }
+
+    /// A file sink for the output, with the container and codecs picked by the flags or the extension.
+    fn get_file_sinktype(&self, encoder: EncoderSettings) -> Result<SinkType, Error> {
+        let container = match self.flag_container {
+            Some(ref name) => Container::from_name(name)?,
+            None => Container::from_path(&self.arg_output_mp4)?
+        };
+
+        let (default_video, default_audio) = container.default_codecs();
+        let video_codec = match self.flag_video_codec {
+            Some(ref name) => VideoCodec::from_name(name)?,
+            None => default_video
+        };
+        let audio_codec = match self.flag_audio_codec {
+            Some(ref name) => AudioCodec::from_name(name)?,
+            None => default_audio
+        };
+        container.check(video_codec, audio_codec)?;
+
+        Ok(SinkType::File {
+            uri: self.arg_output_mp4.clone(),
+            container: container,
+            video_codec: video_codec,
+            audio_codec: audio_codec,
+            encoder: encoder
+        })
+    }
+
+    /// One sink per run of the pipeline.  Two pass converts run it twice.
+    fn get_sinktypes(&self) -> Result<Vec<SinkType>, Error> {
+        Ok(if self.cmd_preview {
+            vec![SinkType::playback]
+        } else if self.cmd_frames {
+            vec![SinkType::ImageSequence {
+                pattern: self.arg_pattern.clone(),
+                format: ImageFormat::from_path(&self.arg_pattern)?
+            }]
+        } else if self.cmd_convert && self.flag_lossless.is_some() {
+            vec![SinkType::Lossless {
+                uri: self.arg_output_mp4.clone(),
+                format: LosslessFormat::from_name(self.flag_lossless.as_ref().unwrap())?
+            }]
+        } else if self.cmd_convert && self.flag_two_pass {
+            let settings = self.get_encoder_settings()?;
+            let stats_file = self.get_stats_file();
+            vec![
+                self.get_file_sinktype(settings.for_pass(EncodePass::First, &stats_file)?)?,
+                self.get_file_sinktype(settings.for_pass(EncodePass::Second, &stats_file)?)?
+            ]
+        } else if self.cmd_convert {
+            vec![self.get_file_sinktype(self.get_encoder_settings()?)?]
+        } else {
+            vec![]
+        })
+    }
 }
 
 fn example_main() -> Result<(), Error> {
@@ -94,57 +334,183 @@ fn example_main() -> Result<(), Error> {
                             .and_then(|d| d.deserialize())
                             .unwrap_or_else(|e| e.exit());
 
+    init_logging(args.flag_v, args.flag_q);
+    Cancel::install()?;
     gstreamer::init()?;
 
 
-    let sinktype = args.get_sinktype();
-    if sinktype.is_some() {
-        let sinktype = sinktype.unwrap();
-        let uri_from = args.arg_input_mp4.as_str();
+    let registry = TransformRegistry::builtin();
+
+    let sinktypes = args.get_sinktypes()?;
+    if args.flag_list_transforms {
+        for entry in registry.entries() {
+            println!("{:<12} {}", entry.name, entry.description);
+        }
+    } else if !sinktypes.is_empty() {
         let uri_to = args.arg_output_mp4.as_str();
+        let params = match args.flag_config {
+            Some(ref path) => TransformParams::load(path)?,
+            None => TransformParams::default()
+        };
+        let canvas = match args.flag_canvas {
+            Some(ref spec) => Some(VideoFormat::from_canvas_spec(spec).ok_or(InvalidCanvas(spec.clone()))?),
+            None => None
+        };
+
+        let range = args.get_range()?;
+        let preroll = parse_time(&args.flag_preroll)?;
+        let progress_mode = ProgressMode::from_name(&args.flag_progress)?;
+        let _stats_files = StatsFiles(if args.flag_two_pass { Some(args.get_stats_file()) } else { None });
+
+        for sinktype in sinktypes {
+            // every run gets a fresh transform, so each pass renders identical frames
+            let transform = registry.create(&args.flag_transform, &params)?;
+
+            debug!("Creating framesource");
+            let sink;
+            let source_result;
+            {
+                let (source, srx, arx, vrx) = FrameSource::from_inputs(args.get_source_input(), args.get_audio_input(), args.flag_mix_audio)?;
+                debug!("Spawning framesink");
+                let progress = Progress::new(progress_mode, range.start, range.end);
+                let duration = progress.duration();
+                sink = FrameSink::spawn(sinktype, transform, canvas, range, progress, srx, arx, vrx);
+                if !range.is_all() {
+                    source.seek_range((range.start - preroll).max(0f64), range.end)?;
+                } else {
+                    source.preroll()?;
+                }
+                *duration.lock().unwrap() = source.duration();
+                debug!("Running source pipeline...");
+                // source.add_video_handler(|frame, timecode| {});
+                // source.add_audio_handler(|sample, timecode| {});
+                source_result = PipelineUtils::run_source(&source);
+            }
+
+            debug!("Done!  Waiting for sink pipeline to finish...");
+            // the sink is always joined, so a failed source still finishes the output file
+            let sink_result = PipelineUtils::join(sink, "sink");
+            source_result?;
+            sink_result??;
+
+            // a cancelled first pass leaves nothing for the second
+            if Cancel::is_cancelled() {
+                break;
+            }
+        }
+
+        if Cancel::is_cancelled() {
+            Err(Cancelled)?;
+        }
+    } else if args.cmd_still {
+        let at = parse_time(&args.flag_at)?;
+        let preroll = parse_time(&args.flag_preroll)?;
+        let image_format = ImageFormat::from_path(&args.arg_output_png)?;
+        let params = match args.flag_config {
+            Some(ref path) => TransformParams::load(path)?,
+            None => TransformParams::default()
+        };
+        let transform = registry.create(&args.flag_transform, &params)?;
+
+        debug!("Creating framesource");
+        let still;
+        let sink;
+        let source_result;
+        {
+            let (source, srx, arx, vrx) = FrameSource::from_inputs(args.get_source_input(), args.get_audio_input(), args.flag_mix_audio)?;
+            let (join, srx, arx, vrx) = FrameStill::spawn(transform, at, srx, arx, vrx);
+            still = join;
+
+            let sinktype = SinkType::ImageSequence {
+                pattern: args.arg_output_png.clone(),
+                format: image_format
+            };
+            sink = FrameSink::spawn(sinktype, IdentityTransform::new(), None, TimeRange::all(), Progress::quiet(), srx, arx, vrx);
+
+            source.seek((at - preroll).max(0f64))?;
+            source_result = PipelineUtils::run_source(&source);
+        }
+
+        let still_result = PipelineUtils::join(still, "still");
+        let sink_result = PipelineUtils::join(sink, "sink");
+        if Cancel::is_cancelled() {
+            Err(Cancelled)?;
+        }
+        source_result?;
+        still_result??;
+        sink_result??;
+    } else if args.cmd_trace {
+        let measure = TraceMeasure::from_name(&args.arg_measure)?;
+        let format = TraceFormat::from_name(&args.flag_format)?;
+        let output: Box<dyn Write + Send> = if args.flag_output == "-" {
+            Box::new(io::stdout())
+        } else {
+            Box::new(BufWriter::new(File::create(&args.flag_output)?))
+        };
 
-        println!("Creating framesource");
-        let mut sink;
+        debug!("Creating framesource");
+        let trace;
+        let source_result;
         {
-            let (source, arx, vrx) = FrameSource::new(uri_from)?;
-            println!("Spawning framesink");
-            sink = FrameSink::spawn(sinktype, FrameTransformImpl::new(), arx, vrx);
-            println!("Running source pipeline...");
-            // source.add_video_handler(|frame, timecode| {});
-            // source.add_audio_handler(|sample, timecode| {});
-            PipelineUtils::start(&source)?;
-            PipelineUtils::message(&source)?;
-            PipelineUtils::stop(&source)?;
+            let (source, srx, arx, vrx) = FrameSource::from_input(args.get_source_input())?;
+            debug!("Spawning trace for measure {}", measure.name());
+            trace = FrameTrace::spawn(MeasureTrace::new(measure, format, output), srx, arx, vrx);
+            source_result = PipelineUtils::run_source(&source);
         }
 
-        println!("Done!  Waiting for sink pipeline to finish...");
-        sink.join();
+        debug!("Done!  Waiting for trace to finish...");
+        let trace_result = PipelineUtils::join(trace, "trace");
+        source_result?;
+        trace_result?.finish()?;
+        if Cancel::is_cancelled() {
+            Err(Cancelled)?;
+        }
     } else {
-        println!("Unknown command!");
+        error!("Unknown command!");
     }
 
     
 
     
-    println!("Done!");
+    debug!("Done!");
     Ok(())
 }
 
 fn main() {
-    let main_loop = glib::MainLoop::new(glib::MainContext::default().as_ref(), false);
+    let main_loop = glib::MainLoop::new(None, false);
     let main_loop_end = main_loop.clone();
 
     let join_program = thread::spawn(move || {
-        println!("Running program");
-        match osx::run(example_main) {
-            Ok(_) => println!("Success!"),
-            Err(e) => println!("Error! {}", e)
-        }
+        debug!("Running program");
+        // quit the main loop even if the program panics, so the process can exit
+        let result = panic::catch_unwind(|| osx::run(example_main));
         main_loop_end.quit();
+        result
     });
 
     main_loop.run();
-    join_program.join();
+    let code = match join_program.join() {
+        Ok(Ok(Ok(_))) => {
+            debug!("Success!");
+            0
+        },
+        Ok(Ok(Err(ref e))) if e.downcast_ref::<Cancelled>().is_some() => {
+            warn!("{}", e);
+            130
+        },
+        Ok(Ok(Err(e))) => {
+            error!("Error! {}", e);
+            for cause in e.chain().skip(1) {
+                error!("  caused by: {}", cause);
+            }
+            1
+        },
+        _ => {
+            error!("Error! The program panicked");
+            101
+        }
+    };
+    process::exit(code);
 }
 
 
@@ -1,6 +1,6 @@
 
-use audio::audio_frame::*;
-use measures::*;
+use crate::audio::audio_frame::*;
+use crate::measures::*;
 
 #[derive(Clone)]
 pub struct ChunkMeasure {
@@ -14,7 +14,7 @@ pub struct ChunkMeasure {
 impl ChunkMeasure {
     pub fn new(window: f64) -> AudioAbsChunkMeasure {
         AudioAbsChunkMeasure {
-            value: ::std::f64::NAN;
+            value: f64::NAN;
             chunk_sum: 0f64,
             chunk_sum: 0f64,
             chunk_size: 0,
@@ -1,4 +1,4 @@
-use measures::*;
+use crate::measures::*;
 
 // mod tests {
 //     use measures::test_measure::TestMeasure;
@@ -79,8 +79,8 @@ impl ExpDecayMeasure {
         ExpDecayMeasure {
             up_timeconst: up_timeconst,
             down_timeconst: down_timeconst,
-            last_time: ::std::f64::NAN,
-            last_value: ::std::f64::NAN
+            last_time: f64::NAN,
+            last_value: f64::NAN
         }
     }
 }
@@ -1,5 +1,5 @@
-use measures::mean_window_measure::*;
-use measures::*;
+use crate::measures::mean_window_measure::*;
+use crate::measures::*;
 use stats::mean;
 
 pub struct LogRatioMeasure {
@@ -28,7 +28,7 @@ impl<'a> LogRatioMeasure {
 impl<'a> Measure<(f64, f64), f64> for LogRatioMeasure {
     fn value(&mut self, (edge, avg): (f64, f64)) -> f64 {    
         if avg == 0f64 {
-            return ::std::f64::NAN;   
+            return f64::NAN;   
         }
         
         // we take the natural log to help with smoothing/normalization
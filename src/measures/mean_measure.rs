@@ -1,4 +1,4 @@
-use measures::*;
+use crate::measures::*;
 
 pub struct MeanMeasure {
     sum: f64,
@@ -17,7 +17,7 @@ impl MeanMeasure {
 impl StatefulMeasure<f64,f64> for MeanMeasure {
     fn value(&mut self) -> f64 {
         if self.n == 0 {
-            return ::std::f64::NAN;
+            return f64::NAN;
         }
 
         self.sum / (self.n as f64)
@@ -1,7 +1,7 @@
-use audio::audio_format::*;
-use audio::audio_frame::*;
-use pipeline::queue_buf::*;
-use measures::*;
+use crate::audio::audio_format::*;
+use crate::audio::audio_frame::*;
+use crate::pipeline::queue_buf::*;
+use crate::measures::*;
 use stats::mean;
 
 pub struct MeanWindowMeasure {
@@ -1,5 +1,5 @@
-use audio::audio_frame::*;
-use video::video_frame::*;
+use crate::audio::audio_frame::*;
+use crate::video::video_frame::*;
 
 // pub trait AudioFrameProcessor {
 //     fn process_audio(&mut self, af: &AudioFrame);
@@ -18,7 +18,7 @@ use video::video_frame::*;
 //       sigmoid
 // edge measure
 
-type MeasureF64 = Measure<TimedData<f64>, TimedData<f64>>;
+type MeasureF64 = dyn Measure<TimedData<f64>, TimedData<f64>>;
 pub struct TimedData<T> {
     pub data: T,
     pub duration: f64
@@ -34,12 +34,12 @@ impl TimedData<f64> {
 }
 
 pub trait Measure<I, O> {
-    fn value(&mut self, I) -> O;
+    fn value(&mut self, input: I) -> O;
 }
 
 pub trait StatefulMeasure<I, O> {
     fn value(&mut self) -> O;
-    fn update(&mut self, I);
+    fn update(&mut self, input: I);
 }
 
 // pub trait Measure<Frame> {
@@ -6,4 +6,4 @@ pub mod mean_measure;
 pub mod mean_window_measure;
 mod measure;
 
-pub use measures::measure::*;
\ No newline at end of file
+pub use crate::measures::measure::*;
\ No newline at end of file
@@ -1,4 +1,4 @@
-use measures::*;
+use crate::measures::*;
 use stats::OnlineStats;
 
 pub enum SigmoidRange {
@@ -1,4 +1,4 @@
-use measures::*;
+use crate::measures::*;
 
 pub struct TestMeasure {
     pub vals: Vec<f64>,
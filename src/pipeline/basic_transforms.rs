@@ -0,0 +1,78 @@
+use crate::audio::audio_frame::*;
+use crate::video::video_frame::*;
+
+use crate::pipeline::frame_transform::*;
+use crate::pipeline::transform_chain::*;
+
+/// Passes every frame through untouched.
+pub struct IdentityTransform {
+
+}
+
+impl IdentityTransform {
+    pub fn new() -> IdentityTransform {
+        IdentityTransform {}
+    }
+}
+
+impl FrameTransform for IdentityTransform {
+    fn process_audio_frame(&mut self, _aframe: &mut AudioFrame, _atime: f64) {}
+
+    fn process_video_frame(&mut self, _vframe: &mut VideoFrame, _vtime: f64) {}
+}
+
+const OVERLAY_BAR_HEIGHT: usize = 8;
+const OVERLAY_MARGIN: usize = 8;
+
+/// Paints the audio levels published by an AudioLevelsStage as bars in the top left corner.
+/// Volume is drawn in white, audio edges in red.
+pub struct LevelsOverlay {
+    levels: SharedAudioLevels
+}
+
+impl LevelsOverlay {
+    pub fn new(levels: SharedAudioLevels) -> LevelsOverlay {
+        LevelsOverlay {
+            levels: levels
+        }
+    }
+
+    /// Levels analysis followed by the overlay, ready to append to any chain.
+    pub fn with_analysis() -> TransformChain {
+        let analysis = AudioLevelsStage::new();
+        let overlay = LevelsOverlay::new(analysis.levels());
+        TransformChain::new().then(analysis).then(overlay)
+    }
+
+    fn draw_bar(vframe: &mut VideoFrame, row: usize, value: f64, yuv: (u8, u8, u8)) {
+        let frame_width = vframe.format.width as usize;
+        let frame_height = vframe.format.height as usize;
+        let bar_width = frame_width / 4;
+
+        // levels are in [-1, 1]
+        let fill = ((value + 1f64) / 2f64).max(0f64).min(1f64);
+        let filled = (fill * bar_width as f64) as usize;
+
+        let top = OVERLAY_MARGIN + row * (OVERLAY_BAR_HEIGHT + OVERLAY_MARGIN);
+        for y in top..::std::cmp::min(top + OVERLAY_BAR_HEIGHT, frame_height) {
+            for x in OVERLAY_MARGIN..::std::cmp::min(OVERLAY_MARGIN + bar_width, frame_width) {
+                let idx = 4 * (y * frame_width + x);
+                let pixel = &mut vframe.data[idx..idx+4];
+                let (py, pu, pv) = if x - OVERLAY_MARGIN < filled { yuv } else { (16u8, 128u8, 128u8) };
+                pixel[1] = py;
+                pixel[2] = pu;
+                pixel[3] = pv;
+            }
+        }
+    }
+}
+
+impl FrameTransform for LevelsOverlay {
+    fn process_audio_frame(&mut self, _aframe: &mut AudioFrame, _atime: f64) {}
+
+    fn process_video_frame(&mut self, vframe: &mut VideoFrame, _vtime: f64) {
+        let levels = *self.levels.lock().unwrap();
+        Self::draw_bar(vframe, 0, levels.volume, (235u8, 128u8, 128u8));
+        Self::draw_bar(vframe, 1, levels.edge, (82u8, 90u8, 240u8));
+    }
+}
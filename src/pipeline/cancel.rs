@@ -0,0 +1,35 @@
+use std::process;
+use std::sync::atomic::{AtomicBool, Ordering};
+
+
+use log::warn;
+use anyhow::Error;
+
+static CANCELLED: AtomicBool = AtomicBool::new(false);
+
+#[derive(Debug, thiserror::Error)]
+#[error("Cancelled, the output was finished early")]
+pub struct Cancelled;
+
+/// Process wide cancellation, requested with SIGINT or SIGTERM.
+pub struct Cancel {
+
+}
+
+impl Cancel {
+    /// Installs the signal handler.  The first signal asks the workers to stop reading input,
+    /// so the sinks can end their streams and finish writing.  A second one exits right away.
+    pub fn install() -> Result<(), Error> {
+        ctrlc::set_handler(|| {
+            if CANCELLED.swap(true, Ordering::SeqCst) {
+                process::exit(130);
+            }
+            warn!("Cancelling, finishing the output.  Interrupt again to quit immediately");
+        })?;
+        Ok(())
+    }
+
+    pub fn is_cancelled() -> bool {
+        CANCELLED.load(Ordering::SeqCst)
+    }
+}
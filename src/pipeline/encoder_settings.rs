@@ -0,0 +1,308 @@
+use crate::pipeline::transform_params::*;
+
+use crate::video::video_format::*;
+
+use gstreamer::prelude::*;
+
+use log::info;
+use serde::{Serialize, Deserialize};
+use anyhow::Error;
+
+#[derive(Debug, thiserror::Error)]
+#[error("Unknown rate control {}, expected abr, cbr, crf or quantizer", .0)]
+pub struct UnknownRateControl(pub String);
+
+#[derive(Debug, thiserror::Error)]
+#[error("Unknown x264 speed preset {}, expected one of: {}", .0, SPEED_PRESETS.join(", "))]
+pub struct UnknownSpeedPreset(pub String);
+
+#[derive(Debug, thiserror::Error)]
+#[error("Unknown x264 tune {}, expected at most one of {} and any of {}, joined with +", .0, PSY_TUNES.join(", "), TUNE_FLAGS.join(", "))]
+pub struct UnknownTune(pub String);
+
+#[derive(Debug, thiserror::Error)]
+#[error("Unknown h264 profile {}, expected one of: {}", .0, PROFILES.join(", "))]
+pub struct UnknownProfile(pub String);
+
+#[derive(Debug, thiserror::Error)]
+#[error("Two pass encoding needs abr rate control, got {}", .0)]
+pub struct TwoPassRateControl(pub String);
+
+/// Which run of the pipeline an encoder is part of.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum EncodePass {
+    Single,
+    /// analysis pass, which writes the stats file and discards the encoded video
+    First,
+    /// final pass, which reads the stats file
+    Second
+}
+
+impl Default for EncodePass {
+    fn default() -> EncodePass {
+        EncodePass::Single
+    }
+}
+
+/// How x264 spends bits.
+#[derive(Copy, Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum RateControl {
+    /// average bitrate, a single pass aiming at `bitrate`.
+    /// For h264 this is x264enc's single pass bitrate mode, the same as cbr
+    Abr,
+    /// constant bitrate
+    Cbr,
+    /// constant quality, using `quantizer` as the rate factor
+    Crf,
+    /// constant quantizer
+    Quantizer
+}
+
+impl RateControl {
+    pub fn from_name(name: &str) -> Result<RateControl, Error> {
+        match name {
+            "abr" => Ok(RateControl::Abr),
+            "cbr" => Ok(RateControl::Cbr),
+            "crf" => Ok(RateControl::Crf),
+            "quantizer" => Ok(RateControl::Quantizer),
+            _ => Err(UnknownRateControl(name.to_string()))?
+        }
+    }
+
+    /// The x264enc `pass` nick.
+    fn pass(&self) -> &'static str {
+        match self {
+            &RateControl::Abr | &RateControl::Cbr => "cbr",
+            &RateControl::Crf => "qual",
+            &RateControl::Quantizer => "quant"
+        }
+    }
+
+    fn uses_bitrate(&self) -> bool {
+        match self {
+            &RateControl::Abr | &RateControl::Cbr => true,
+            &RateControl::Crf | &RateControl::Quantizer => false
+        }
+    }
+}
+
+/// Video encoder settings for convert.
+/// Set from the `[encoder]` table of the `--config` file, and overridden by command line flags.
+/// Anything left unset is derived from the output VideoFormat.
+/// The speed preset, tune and profile only apply to h264.
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct EncoderSettings {
+    pub rate_control: Option<RateControl>,
+    /// kbit/s, for abr and cbr
+    pub bitrate: Option<u32>,
+    /// rate factor or quantizer, for crf and quantizer.  0-50 for h264, 0-63 for vp8 and vp9
+    pub quantizer: Option<u32>,
+    /// x264 speed preset, e.g. veryfast, medium or slow
+    pub speed_preset: Option<String>,
+    /// x264 tuning, e.g. film or fastdecode+zerolatency
+    pub tune: Option<String>,
+    /// h264 profile, e.g. baseline, main or high
+    pub profile: Option<String>,
+    /// maximum distance between keyframes, in frames
+    pub keyframe_interval: Option<u32>,
+    pub bframes: Option<u32>,
+
+    #[serde(skip)]
+    pub pass: EncodePass,
+    /// x264 stats file shared by both passes of a two pass encode
+    #[serde(skip)]
+    pub stats_file: Option<String>
+}
+
+// the original settings, based on youtube upload recommendations for 1080p60.
+// https://support.google.com/youtube/answer/1722171?hl=en
+const REFERENCE_BITRATE: f64 = 15630f64;
+const REFERENCE_PIXELS: f64 = 1920f64 * 1080f64;
+const REFERENCE_FRAME_RATE: f64 = 60f64;
+
+const MIN_BITRATE: u32 = 500;
+const DEFAULT_QUANTIZER: u32 = 21;
+const DEFAULT_VPX_QUANTIZER: u32 = 31;
+const DEFAULT_PROFILE: &'static str = "high";
+const KEYFRAME_SECONDS: f64 = 2f64;
+
+// the names x264enc accepts, checked up front as set_property_from_str panics on anything else
+const SPEED_PRESETS: &[&str] = &["ultrafast", "superfast", "veryfast", "faster", "fast", "medium", "slow", "slower", "veryslow", "placebo"];
+/// The x264enc `psy-tune` enum.
+const PSY_TUNES: &[&str] = &["film", "animation", "grain", "psnr", "ssim"];
+/// The x264enc `tune` flags.
+const TUNE_FLAGS: &[&str] = &["stillimage", "fastdecode", "zerolatency"];
+const PROFILES: &[&str] = &["baseline", "constrained-baseline", "main", "high"];
+
+/// Splits a tune such as `film+zerolatency` into the x264enc tune flags and psy tune.
+fn split_tune(tune: &str) -> Result<(Vec<&str>, Option<&str>), Error> {
+    let mut flags = Vec::new();
+    let mut psy_tune = None;
+    for name in tune.split('+') {
+        if TUNE_FLAGS.contains(&name) {
+            flags.push(name);
+        } else if PSY_TUNES.contains(&name) && psy_tune.is_none() {
+            psy_tune = Some(name);
+        } else {
+            Err(UnknownTune(tune.to_string()))?;
+        }
+    }
+
+    Ok((flags, psy_tune))
+}
+
+impl EncoderSettings {
+    /// Reads the `[encoder]` table of a config file.  The look parameters in the same file are ignored,
+    /// but unknown keys in the table are an error.
+    pub fn load(path: &str) -> Result<EncoderSettings, Error> {
+        let config = load_config(path)?;
+        match config.get(ENCODER_TABLE) {
+            Some(table) => Ok(serde_json::from_value(table.clone())?),
+            None => Ok(EncoderSettings::default())
+        }
+    }
+
+    /// Checks the speed preset, tune and profile names.
+    pub fn validate(&self) -> Result<(), Error> {
+        if let Some(ref preset) = self.speed_preset {
+            if !SPEED_PRESETS.contains(&preset.as_str()) {
+                Err(UnknownSpeedPreset(preset.clone()))?;
+            }
+        }
+        if let Some(ref tune) = self.tune {
+            split_tune(tune)?;
+        }
+        if let Some(ref profile) = self.profile {
+            if !PROFILES.contains(&profile.as_str()) {
+                Err(UnknownProfile(profile.clone()))?;
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Settings for one pass of a two pass encode.
+    pub fn for_pass(&self, pass: EncodePass, stats_file: &str) -> Result<EncoderSettings, Error> {
+        let rate_control = self.rate_control();
+        if rate_control != RateControl::Abr {
+            Err(TwoPassRateControl(format!("{:?}", rate_control).to_lowercase()))?;
+        }
+
+        Ok(EncoderSettings {
+            pass: pass,
+            stats_file: Some(stats_file.to_string()),
+            ..self.clone()
+        })
+    }
+
+    pub fn rate_control(&self) -> RateControl {
+        self.rate_control.unwrap_or(RateControl::Abr)
+    }
+
+    /// Keeps the bits per pixel of the 1080p60 reference.
+    /// Frame rate counts for less than resolution, as consecutive frames at high rates are more alike.
+    pub fn bitrate(&self, format: &VideoFormat) -> u32 {
+        self.bitrate.unwrap_or_else(|| {
+            let pixels = format.pixel_count as f64 / REFERENCE_PIXELS;
+            let rate = (format.frame_rate / REFERENCE_FRAME_RATE).powf(0.75f64);
+            ::std::cmp::max(MIN_BITRATE, (REFERENCE_BITRATE * pixels * rate).round() as u32)
+        })
+    }
+
+    pub fn quantizer(&self) -> u32 {
+        self.quantizer.unwrap_or(DEFAULT_QUANTIZER)
+    }
+
+    pub fn profile(&self) -> &str {
+        self.profile.as_ref().map(|e| e.as_str()).unwrap_or(DEFAULT_PROFILE)
+    }
+
+    pub fn keyframe_interval(&self, format: &VideoFormat) -> u32 {
+        self.keyframe_interval.unwrap_or_else(|| {
+            ::std::cmp::max(1, (format.frame_rate * KEYFRAME_SECONDS).round() as u32)
+        })
+    }
+
+    /// Configures an x264enc for the given output format.
+    pub fn apply(&self, x264enc: &gstreamer::Element, format: &VideoFormat) -> Result<(), Error> {
+        let rate_control = self.rate_control();
+        match self.pass {
+            EncodePass::Single => x264enc.set_property_from_str("pass", rate_control.pass()),
+            EncodePass::First => x264enc.set_property_from_str("pass", "pass1"),
+            EncodePass::Second => x264enc.set_property_from_str("pass", "pass2")
+        }
+        if let Some(ref stats_file) = self.stats_file {
+            x264enc.set_property("multipass-cache-file", stats_file.as_str());
+        }
+        if rate_control.uses_bitrate() {
+            x264enc.set_property("bitrate", self.bitrate(format));
+        } else {
+            x264enc.set_property("quantizer", self.quantizer());
+        }
+
+        if let Some(ref preset) = self.speed_preset {
+            x264enc.set_property_from_str("speed-preset", preset);
+        }
+        if let Some(ref tune) = self.tune {
+            let (flags, psy_tune) = split_tune(tune)?;
+            if !flags.is_empty() {
+                x264enc.set_property_from_str("tune", &flags.join("+"));
+            }
+            if let Some(psy_tune) = psy_tune {
+                x264enc.set_property_from_str("psy-tune", psy_tune);
+            }
+        }
+
+        x264enc.set_property("key-int-max", self.keyframe_interval(format));
+        x264enc.set_property("interlaced", false);
+        // baseline supports neither b-frames nor cabac
+        let baseline = self.profile().ends_with("baseline");
+        x264enc.set_property("bframes", self.bframes.unwrap_or(if baseline { 0u32 } else { 2u32 }));
+        x264enc.set_property("cabac", !baseline);
+
+        info!(target: "sink", "Encoding {:?} pass with {:?}, {} kbit/s, quantizer {}, profile {}, keyframes every {} frames",
+            self.pass, rate_control, self.bitrate(format), self.quantizer(), self.profile(), self.keyframe_interval(format));
+        Ok(())
+    }
+
+    /// Configures a vp8enc or vp9enc for the given output format.
+    pub fn apply_vpx(&self, vpxenc: &gstreamer::Element, format: &VideoFormat) -> Result<(), Error> {
+        let rate_control = self.rate_control();
+        let end_usage = match rate_control {
+            RateControl::Abr => "vbr",
+            RateControl::Cbr => "cbr",
+            RateControl::Crf => "cq",
+            RateControl::Quantizer => "q"
+        };
+        vpxenc.set_property_from_str("end-usage", end_usage);
+        vpxenc.set_property("target-bitrate", (self.bitrate(format) * 1000) as i32);
+        let quantizer = self.quantizer.unwrap_or(DEFAULT_VPX_QUANTIZER);
+        if !rate_control.uses_bitrate() {
+            vpxenc.set_property("cq-level", quantizer as i32);
+        }
+
+        match self.pass {
+            EncodePass::Single => vpxenc.set_property_from_str("multipass-mode", "one-pass"),
+            EncodePass::First => vpxenc.set_property_from_str("multipass-mode", "first-pass"),
+            EncodePass::Second => vpxenc.set_property_from_str("multipass-mode", "last-pass")
+        }
+        if let Some(ref stats_file) = self.stats_file {
+            vpxenc.set_property("multipass-cache-file", stats_file.as_str());
+        }
+
+        vpxenc.set_property("keyframe-max-dist", self.keyframe_interval(format) as i32);
+
+        info!(target: "sink", "Encoding {:?} pass with {:?}, {} kbit/s, quantizer {}, keyframes every {} frames",
+            self.pass, rate_control, self.bitrate(format), quantizer, self.keyframe_interval(format));
+        Ok(())
+    }
+
+    /// Caps between the encoder and the muxer, which select the profile.
+    pub fn output_caps(&self) -> gstreamer::Caps {
+        gstreamer::Caps::builder("video/x-h264")
+            .field("profile", self.profile())
+            .build()
+    }
+}
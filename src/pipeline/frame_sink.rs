@@ -1,35 +1,65 @@
-use pipeline::frame_source::*;
-use pipeline::frame_transform::*;
-use pipeline::pipeline_utils::*;
+use crate::pipeline::cancel::*;
+use crate::pipeline::encoder_settings::*;
+use crate::pipeline::frame_source::*;
+use crate::pipeline::frame_transform::*;
+use crate::pipeline::output_format::*;
+use crate::pipeline::pipeline_utils::*;
+use crate::pipeline::progress::*;
 
-use audio::audio_buffer::*;
-use video::video_buffer::*;
+use crate::audio::audio_buffer::*;
+use crate::video::video_buffer::*;
 
-use audio::audio_format::*;
-use video::video_format::*;
+use crate::audio::audio_format::*;
+use crate::video::video_format::*;
 
-use audio::audio_iter::*;
-use video::video_iter::*;
+use crate::audio::audio_iter::*;
+use crate::video::video_iter::*;
 
-use std::f64;
 use std::thread;
 use std::sync::Mutex;
 use std::sync::Arc;
 use std::sync::mpsc::Receiver;
 
 use gstreamer::prelude::*;
-use gstreamer;
-use gstreamer_app;
 
-extern crate failure;
-use failure::Error;
-use cpuprofiler::PROFILER;
+use log::{debug, info};
+use anyhow::Error;
 
 pub enum SinkType {
-    file_mp4(String),
+    File {
+        uri: String,
+        container: Container,
+        video_codec: VideoCodec,
+        audio_codec: AudioCodec,
+        encoder: EncoderSettings
+    },
+    /// lossless output for further editing.  The appsrc caps are unchanged, only the encoders differ
+    Lossless {
+        uri: String,
+        format: LosslessFormat
+    },
+    /// one numbered image per frame, e.g. frames/%05d.png, with the audio in a WAV file next to them
+    ImageSequence {
+        pattern: String,
+        format: ImageFormat
+    },
     playback
 }
 
+impl SinkType {
+    /// A file sink with the container's default codecs.
+    pub fn file(uri: &str, container: Container, encoder: EncoderSettings) -> SinkType {
+        let (video_codec, audio_codec) = container.default_codecs();
+        SinkType::File {
+            uri: uri.to_string(),
+            container: container,
+            video_codec: video_codec,
+            audio_codec: audio_codec,
+            encoder: encoder
+        }
+    }
+}
+
 pub struct FrameSink {
     pipeline: gstreamer::Pipeline,
     muxer: Option<gstreamer::Element>,
@@ -45,29 +75,59 @@ impl IntoPipeline for FrameSink {
 }
 
 impl FrameSink {
+    /// Spawns the transform thread.
+    /// Only the branches for streams the source found are built.
+    /// If the source has no video and a canvas format is given, blank canvas frames are
+    /// generated for the transform to paint, for as long as the audio lasts.
+    /// Audio before the start of the range is pre-roll.  It's fed to the transform, but not output.
+    /// Video before the start is skipped.  The output is timed from the start of the range.
+    /// The thread returns the first error from the output pipeline or from pushing into it.
+    /// On cancellation the input is dropped and the streams are ended, so the output is still finished.
     pub fn spawn<T: FrameTransform + Send + 'static>(stype: SinkType, transform: 
-    T, arx: Receiver<Arc<Mutex<AudioBuffer>>>, vrx: Receiver<Arc<Mutex<VideoBuffer>>>) -> ::std::thread::JoinHandle<()> {
+    T, canvas: Option<VideoFormat>, range: TimeRange, progress: Progress, srx: Receiver<SourceStreams>,
+    arx: Receiver<Arc<Mutex<AudioBuffer>>>, vrx: Receiver<Arc<Mutex<VideoBuffer>>>) -> ::std::thread::JoinHandle<Result<(), Error>> {
         // get an owned string so the &str doesn't need to exist for the static lifetime...
         // we unpack it on the other side
         let mut transform = transform;
+        let mut progress = progress;
 
         thread::spawn(move || {
-            let mut sink = FrameSink::new(stype).unwrap();
-            println!("Got framesink!  Waiting for Audio/Video format");
+            let mut sink = FrameSink::new(stype)?;
+            debug!(target: "sink", "Got framesink!  Waiting for source streams");
+
+            let streams = srx.recv().unwrap_or(SourceStreams::none());
+            let use_canvas = !streams.video && streams.audio && canvas.is_some();
+            info!(target: "sink", "Got source streams {:?}, canvas: {}", streams, use_canvas);
 
             let mut audio_sink = None;
             let mut video_sink = None;
+            let mut last_audio_format = AudioFormat::empty();
+            let mut last_video_format = VideoFormat::empty();
             //TODO: refactor as closure with generic bounds on AudioIter/VideoIter
             let mut audio_iter = AudioIter::new(arx);
-            let mut video_iter = VideoIter::new(vrx);
+            let mut video_iter = if use_canvas {
+                VideoIter::canvas(canvas.unwrap())
+            } else {
+                VideoIter::new(vrx)
+            };
             
-            let audio_format = audio_iter.format();
-            println!("Got audio format {:?}!", audio_format);
-            audio_sink = Some(sink.add_audio_sink(&audio_format.unwrap()).unwrap());
+            if streams.audio {
+                let audio_format = audio_iter.format();
+                debug!(target: "sink", "Got audio format {:?}!", audio_format);
+                if let Some(audio_format) = audio_format {
+                    audio_sink = Some(sink.add_audio_sink(&audio_format)?);
+                    last_audio_format = audio_format;
+                }
+            }
 
-            let video_format = video_iter.format();
-            println!("Got video format {:?}!", video_format);
-            video_sink = Some(sink.add_video_sink(&video_format.unwrap()).unwrap());
+            if streams.video || use_canvas {
+                let video_format = video_iter.format();
+                debug!(target: "sink", "Got video format {:?}!", video_format);
+                if let Some(video_format) = video_format {
+                    video_sink = Some(sink.add_video_sink(&video_format)?);
+                    last_video_format = video_format;
+                }
+            }
             // let audio_iter = FrameIterator::new(arx);
             // let video_iter = FrameIterator::new(vrx);
 
@@ -88,117 +148,184 @@ impl FrameSink {
             let mut vtime = 0f64;
 
             let mut state = 0f64;
-            let mut has_audio_frame = true;
-            let mut has_video_frame = true;
+            let mut has_audio_frame = audio_sink.is_some();
+            let mut has_video_frame = video_sink.is_some();
+            if !has_audio_frame {
+                atime = f64::MAX;
+            }
+            if !has_video_frame {
+                vtime = f64::MAX;
+            }
 
-            PipelineUtils::start(&sink);
+            PipelineUtils::start(&sink)?;
             let join = thread::spawn(move || {
-                println!("Ran pipeline: {:?}", PipelineUtils::message(&sink));
-                println!("Stopping output pipeline");
-                PipelineUtils::stop(&sink);
+                let result = PipelineUtils::message(&sink);
+                debug!(target: "sink", "Ran pipeline: {:?}", result);
+                debug!(target: "sink", "Stopping output pipeline");
+                PipelineUtils::stop(&sink)?;
+                result
             });
-            println!("Write pipeline started!");
+            debug!(target: "sink", "Write pipeline started!");
             // PROFILER.lock().unwrap().start("./my-prof.profile").unwrap();
-            while has_video_frame || has_audio_frame {
-                // oh the CPU branch prediction.. poor CPU..
-                // however, since this program is meant to do offline 'one time' processing, 
-                // the performance is acceptable so far
-                // in dev, I just use short sample videos
+            let mut push_result = Ok(());
+            'frames: while has_video_frame || has_audio_frame {
+                if Cancel::is_cancelled() {
+                    info!(target: "sink", "Cancelled at {:.3}s, finishing output", atime.min(vtime));
+                    break 'frames;
+                }
+
+                // audio is interleaved a whole buffer at a time, so video frames may see audio
+                // up to one buffer ahead of them
                 if atime < vtime {
-                    // println!("Processing audio frame at time {}", atime);
-                    match audio_iter.next_audio_frame() {
-                        Some(mut frame) => {
-                            transform.process_audio_frame(&mut frame, atime);
-                            atime = frame.time;
+                    // println!("Processing audio block at time {}", atime);
+                    match audio_iter.next_audio_block() {
+                        Some(mut block) => {
+                            transform.process_audio_block(&mut block);
+                            atime = block.end_time();
+                            progress.audio_block(atime);
                         },
                         None => {
-                            println!("Out of audio frames");
+                            debug!(target: "sink", "Out of audio frames");
                             has_audio_frame = false;
                             atime = f64::MAX;
+
+                            // the canvas never runs out on its own
+                            if use_canvas {
+                                has_video_frame = false;
+                                video_iter.finish();
+                            }
                         }
                     }
                 } else {
                     // println!("Processing video frame at time {}", atime);
                     match video_iter.next_video_frame() {
                         Some(mut frame) => {
-                            transform.process_video_frame(&mut frame, vtime);
+                            if range.contains(frame.time, frame.format.frame_duration) {
+                                transform.process_video_frame(&mut frame, vtime);
+                                progress.video_frame(frame.time);
+                            }
                             // let rotation = (256f64 * (vtime % 3f64) / 3f64) as u8;
 
                             vtime = frame.time;
                             // frame.data[0] = 8;
                         },
                         None => {
-                            println!("Out of video frames");
+                            debug!(target: "sink", "Out of video frames");
                             has_video_frame = false;
                             vtime = f64::MAX;
                         }
                     }
                 }
 
-                while let Some(buf) = video_iter.next_finished_buffer() {
+                while let Some(mut buf) = video_iter.next_finished_buffer() {
+                    if !range.contains(buf.time, buf.format.frame_duration) {
+                        continue;
+                    }
+                    if range.start > 0f64 {
+                        buf.rebase(range.start);
+                    }
                     // println!("Finishing video buffer at time {}", vtime);
                     // println!("Moving video buffer into appsrc...");
-                    buf.into_appsrc(video_sink.as_mut().unwrap());
+                    if buf.format != last_video_format {
+                        info!(target: "sink", "Video format changed to {:?}", buf.format);
+                        video_sink.as_ref().unwrap().set_caps(Some(&Self::video_caps(&buf.format)));
+                        last_video_format = buf.format;
+                    }
+                    push_result = buf.into_appsrc(video_sink.as_mut().unwrap());
+                    if push_result.is_err() {
+                        break 'frames;
+                    }
                     // println!("Done moving video buffer into appsrc...");
                 }
 
-                while let Some(buf) = audio_iter.next_finished_buffer() {
+                while let Some(mut buf) = audio_iter.next_finished_buffer() {
+                    if buf.end_time() <= range.start {
+                        continue;
+                    }
+                    if range.start > 0f64 {
+                        buf.trim_before(range.start);
+                        buf.rebase(range.start);
+                    }
                     // println!("Finishing audio buffer at time {}", atime);
-                    buf.into_appsrc(audio_sink.as_mut().unwrap());
+                    if buf.format != last_audio_format {
+                        info!(target: "sink", "Audio format changed to {:?}", buf.format);
+                        audio_sink.as_ref().unwrap().set_caps(Some(&Self::audio_caps(&buf.format)));
+                        last_audio_format = buf.format;
+                    }
+                    push_result = buf.into_appsrc(audio_sink.as_mut().unwrap());
+                    if push_result.is_err() {
+                        break 'frames;
+                    }
                 }
             }
 
             // PROFILER.lock().unwrap().stop().unwrap();
-            println!("Finished writing frames");
-            video_sink.unwrap().end_of_stream();
-            audio_sink.unwrap().end_of_stream();
+            progress.finish();
+            debug!(target: "sink", "Finished writing frames");
+            // hanging up on the source stops it, so it doesn't block on a full channel
+            drop(audio_iter);
+            drop(video_iter);
+            // an end of stream that can't be sent means the pipeline already stopped, which the join reports
+            if let Some(video_sink) = video_sink {
+                video_sink.end_of_stream().ok();
+            }
+            if let Some(audio_sink) = audio_sink {
+                audio_sink.end_of_stream().ok();
+            }
 
-            println!("Waiting for pipeline to stop...");
-            join.join();
-            println!("Finished write loop");
+            debug!(target: "sink", "Waiting for pipeline to stop...");
+            // a push usually fails because the pipeline did, which has the better message
+            let pipeline_result = PipelineUtils::join(join, "output pipeline")?;
+            debug!(target: "sink", "Finished write loop");
+            pipeline_result?;
+            push_result
         })
     }
 
     pub fn new(sink_type: SinkType) -> Result<FrameSink, Error> {
-        let pipeline = gstreamer::Pipeline::new("recode-output");
+        let pipeline = gstreamer::Pipeline::with_name("recode-output");
 
         let (mux, vid, aud) = match &sink_type {
-            &SinkType::file_mp4(ref uri) => {
-                let filesink = gstreamer::ElementFactory::make("filesink", None).ok_or(MissingElement("filesink"))?;
-                filesink.set_property("location", &uri)?;
-
-                pipeline.add_many(&[&filesink])?;
+            &SinkType::File { ref uri, container, video_codec, audio_codec, encoder: ref settings } => {
+                container.check(video_codec, audio_codec)?;
 
-                let encoder = gstreamer::ElementFactory::make("mp4mux", None).ok_or(MissingElement("webmmux"))?;
-                // encoder.set_property("streamable", &true)?;
-                encoder.connect_pad_added(move |element, src_pad| {
-                    element.link(&filesink);
-                });
+                // the first pass of a two pass encode only needs the stats file
+                let filesink = if settings.pass == EncodePass::First {
+                    gstreamer::ElementFactory::make("fakesink").build().map_err(|_| MissingElement("fakesink"))?
+                } else {
+                    Self::make_filesink(uri)?
+                };
 
-                pipeline.add_many(&[&encoder])?;
-                (Some(encoder), None, None)
+                (Some(Self::add_muxer(&pipeline, container.muxer(), filesink)?), None, None)
+            },
+            &SinkType::Lossless { ref uri, format } => {
+                // y4m has no muxer, the video and audio branches each write their own file
+                match format.muxer() {
+                    Some(muxer) => (Some(Self::add_muxer(&pipeline, muxer, Self::make_filesink(uri)?)?), None, None),
+                    None => (None, None, None)
+                }
+            },
+            &SinkType::ImageSequence { .. } => {
+                // every branch writes its own files
+                (None, None, None)
             },
             &SinkType::playback => {
-                // let playsink = gstreamer::ElementFactory::make("fakesink", None).ok_or(MissingElement("fakesink"))?;
+                // let playsink = gstreamer::ElementFactory::make("fakesink").build().map_err(|_| MissingElement("fakesink"))?;
                 
-                // let playsink = gstreamer::ElementFactory::make("playsink", None).ok_or(MissingElement("playsink"))?;
+                // let playsink = gstreamer::ElementFactory::make("playsink").build().map_err(|_| MissingElement("playsink"))?;
                 // playsink.set_property_from_str("flags", "soft-colorbalance+soft-volume+text+audio+video+buffer");
-                // let audiosink = gstreamer::ElementFactory::make("autoaudiosink", None).ok_or(MissingElement("autoaudiosink"))?;
-                // let videosink = gstreamer::ElementFactory::make("autovideosink", None).ok_or(MissingElement("autovideosink"))?;
+                // let audiosink = gstreamer::ElementFactory::make("autoaudiosink").build().map_err(|_| MissingElement("autoaudiosink"))?;
+                // let videosink = gstreamer::ElementFactory::make("autovideosink").build().map_err(|_| MissingElement("autovideosink"))?;
                 
                 // playsink.set_property("audio-sink", &audiosink)?;
                 // playsink.set_property("video-sink", &videosink)?;
 
-                // pipeline.add_many(&[&playsink, &audiosink, &videosink])?;
-                // pipeline.add_many(&[&playsink])?;
+                // pipeline.add_many([&playsink, &audiosink, &videosink])?;
+                // pipeline.add_many([&playsink])?;
                 // playsink
-                let vidsink = gstreamer::ElementFactory::make("autovideosink", None).ok_or(MissingElement("autovideosink"))?;
-                vidsink.set_property("sync", &false)?;
-                let audsink = gstreamer::ElementFactory::make("autoaudiosink", None).ok_or(MissingElement("autoaudiosink"))?;
-                vidsink.set_property("sync", &false)?;
-                pipeline.add_many(&[&vidsink, &audsink]);
-
-                (None, Some(vidsink), Some(audsink))
+                // the audio and video sinks are created along with their branches,
+                // so a sink without a stream doesn't block preroll
+                (None, None, None)
             }
         };
 
@@ -212,66 +339,149 @@ impl FrameSink {
     }
 
     pub fn new_mp4(uri: &str) -> Result<FrameSink, Error> {
-        Self::new(SinkType::file_mp4(uri.to_string()))
+        Self::new(SinkType::file(uri, Container::Mp4, EncoderSettings::default()))
+    }
+
+    fn make_filesink(uri: &str) -> Result<gstreamer::Element, Error> {
+        let filesink = gstreamer::ElementFactory::make("filesink").build().map_err(|_| MissingElement("filesink"))?;
+        filesink.set_property("location", uri);
+        Ok(filesink)
+    }
+
+    /// Adds a muxer which writes into the sink.  Streams are linked into it as their branches are added.
+    fn add_muxer(pipeline: &gstreamer::Pipeline, muxer: &'static str, filesink: gstreamer::Element) -> Result<gstreamer::Element, Error> {
+        pipeline.add(&filesink)?;
+
+        let encoder = gstreamer::ElementFactory::make(muxer).build().map_err(|_| MissingElement(muxer))?;
+        // encoder.set_property("streamable", &true)?;
+        encoder.connect_pad_added(move |element, _src_pad| {
+            element.link(&filesink).ok();
+        });
+
+        pipeline.add(&encoder)?;
+        Ok(encoder)
+    }
+
+    /// Links an encoder into the muxer, or into its own file if there is no muxer.
+    fn link_encoder(&self, encoder: &gstreamer::Element, pad: &str, uri: &str) -> Result<(), Error> {
+        match &self.muxer {
+            &Some(ref muxer) => encoder.link_pads(Some("src"), muxer, Some(pad))?,
+            &None => {
+                let filesink = Self::make_filesink(uri)?;
+                self.pipeline.add(&filesink)?;
+                encoder.link(&filesink)?;
+            }
+        }
+        Ok(())
+    }
+
+    fn video_caps(format: &VideoFormat) -> gstreamer::Caps {
+        let mut caps = FrameSource::raw_video_caps();
+        {
+            let mut_structure = caps.get_mut().unwrap().structure_mut(0).unwrap();
+            mut_structure.set("framerate", format.frame_rate_gst_fraction);
+            mut_structure.set("width", format.width);
+            mut_structure.set("height", format.height);
+        }
+        caps
+    }
+
+    fn audio_caps(audio_format: &AudioFormat) -> gstreamer::Caps {
+        let mut caps = FrameSource::raw_audio_caps();
+        {
+            let mut_structure = caps.get_mut().unwrap().structure_mut(0).unwrap();
+            mut_structure.set("format", audio_format.sample_format.caps_name());
+            mut_structure.set("channels", audio_format.channels);
+            mut_structure.set("rate", audio_format.rate);
+            if audio_format.channel_mask != 0 {
+                mut_structure.set("channel-mask", gstreamer::Bitmask::new(audio_format.channel_mask));
+            }
+        }
+        caps
     }
 
     fn add_video_sink(&mut self, format: &VideoFormat) -> Result<gstreamer_app::AppSrc, Error> {
-        let src = gstreamer::ElementFactory::make("appsrc", None).ok_or(MissingElement("appsrc"))?;
+        let src = gstreamer::ElementFactory::make("appsrc").build().map_err(|_| MissingElement("appsrc"))?;
 
         // let info = gstreamer_audio::AudioInfo::new(gstreamer_audio::AUDIO_FORMAT_i32, format.width as u32, format.height as u32)
         //     .fps(format.frame_rate_gst_fraction)
         //     .build()
         //     .expect("Failed to create video info");
 
-        let queue = gstreamer::ElementFactory::make("queue", None).ok_or(MissingElement("queue"))?;
-        let videoconvert = gstreamer::ElementFactory::make("videoconvert", None).ok_or(MissingElement("videoconvert"))?;
+        let queue = gstreamer::ElementFactory::make("queue").build().map_err(|_| MissingElement("queue"))?;
+        let videoconvert = gstreamer::ElementFactory::make("videoconvert").build().map_err(|_| MissingElement("videoconvert"))?;
         let appsrc = src.clone()
             .dynamic_cast::<gstreamer_app::AppSrc>()
             .expect("Source element is expected to be an appsrc!");
         
-        let mut caps = FrameSource::raw_video_caps();
-        {
-            let mut_structure = caps.get_mut().unwrap().get_mut_structure(0).unwrap();
-            mut_structure.set_value("framerate", format.frame_rate_gst_fraction.to_send_value());
-            mut_structure.set_value("width", format.width.to_send_value());
-            mut_structure.set_value("height", format.height.to_send_value());
-        }
-        appsrc.set_caps(&caps);
-        appsrc.set_property_format(gstreamer::Format::Time);
+        appsrc.set_caps(Some(&Self::video_caps(format)));
+        appsrc.set_format(gstreamer::Format::Time);
         appsrc.set_max_bytes(1024*1024*1024);
-        appsrc.set_property_block(true);
+        appsrc.set_block(true);
 
-        self.pipeline.add_many(&[&src, &queue, &videoconvert])?;
+        self.pipeline.add_many([&src, &queue, &videoconvert])?;
 
         src.link(&queue)?;
         queue.link(&videoconvert)?;
 
         match &self.sink_type {
-            &SinkType::file_mp4(_) => {
-                let x264enc = gstreamer::ElementFactory::make("x264enc", None).ok_or(MissingElement("x264enc"))?;
-                // based on youtube upload recommendations for 1080p60.
-                // https://support.google.com/youtube/answer/1722171?hl=en
-                x264enc.set_property("bitrate", &15630u32)?;
-                x264enc.set_property("interlaced", &false)?;
-                x264enc.set_property("bframes", &2u32)?;
-                x264enc.set_property("cabac", &true)?;
-                x264enc.set_property_from_str("pass", &"pass1");
+            &SinkType::File { video_codec, encoder: ref settings, .. } => {
+                let encoder = gstreamer::ElementFactory::make(video_codec.encoder()).build().map_err(|_| MissingElement(video_codec.encoder()))?;
+                // the profile is picked with caps after the encoder.  vpx has none to pick
+                let output_filter = gstreamer::ElementFactory::make("capsfilter").build().map_err(|_| MissingElement("capsfilter"))?;
+                match video_codec {
+                    VideoCodec::H264 => {
+                        settings.apply(&encoder, format)?;
+                        output_filter.set_property("caps", settings.output_caps());
+                    },
+                    VideoCodec::VP8 | VideoCodec::VP9 => {
+                        settings.apply_vpx(&encoder, format)?;
+                    }
+                }
                 
-                self.pipeline.add_many(&[&x264enc])?;
+                self.pipeline.add_many([&encoder, &output_filter])?;
 
-                let convert_i420_caps = gstreamer::Caps::new_simple(
-                    "video/x-raw", 
-                    &[
-                        ("format", &"I420")
-                    ]
-                );
+                let convert_i420_caps = gstreamer::Caps::builder("video/x-raw")
+                    .field("format", "I420")
+                    .build();
 
-                videoconvert.link_filtered(&x264enc, Some(&convert_i420_caps))?;
+                videoconvert.link_filtered(&encoder, &convert_i420_caps)?;
 
-                x264enc.link_pads("src", self.muxer.as_ref().unwrap(), "video_0")?;
+                encoder.link(&output_filter)?;
+                output_filter.link_pads(Some("src"), self.muxer.as_ref().unwrap(), Some("video_0"))?;
+            },
+            &SinkType::Lossless { ref uri, format: lossless } => {
+                let encoder = gstreamer::ElementFactory::make(lossless.video_encoder()).build().map_err(|_| MissingElement(lossless.video_encoder()))?;
+                if lossless == LosslessFormat::H264 {
+                    encoder.set_property_from_str("pass", "quant");
+                    encoder.set_property("quantizer", 0u32);
+                    encoder.set_property_from_str("speed-preset", "veryfast");
+                }
+
+                self.pipeline.add(&encoder)?;
+
+                // 4:4:4 keeps all of the chroma the transform painted
+                let convert_y444_caps = gstreamer::Caps::builder("video/x-raw")
+                    .field("format", "Y444")
+                    .build();
+
+                videoconvert.link_filtered(&encoder, &convert_y444_caps)?;
+                self.link_encoder(&encoder, "video_0", uri)?;
+            },
+            &SinkType::ImageSequence { ref pattern, format: image_format } => {
+                let encoder = gstreamer::ElementFactory::make(image_format.encoder()).build().map_err(|_| MissingElement(image_format.encoder()))?;
+                let multifilesink = gstreamer::ElementFactory::make("multifilesink").build().map_err(|_| MissingElement("multifilesink"))?;
+                multifilesink.set_property("location", pattern.as_str());
+
+                self.pipeline.add_many([&encoder, &multifilesink])?;
+                gstreamer::Element::link_many([&videoconvert, &encoder, &multifilesink])?;
             },
             &SinkType::playback => {
-                videoconvert.link(self.video_sink.as_ref().unwrap())?;
+                let vidsink = gstreamer::ElementFactory::make("autovideosink").build().map_err(|_| MissingElement("autovideosink"))?;
+                vidsink.set_property("sync", false);
+                self.pipeline.add(&vidsink)?;
+                videoconvert.link(&vidsink)?;
+                self.video_sink = Some(vidsink);
             }
         };
 
@@ -279,56 +489,82 @@ impl FrameSink {
     }
     
     pub fn add_audio_sink(&mut self, audio_format: &AudioFormat) -> Result<gstreamer_app::AppSrc, Error> {
-        let src = gstreamer::ElementFactory::make("appsrc", None).ok_or(MissingElement("appsrc"))?;
+        let src = gstreamer::ElementFactory::make("appsrc").build().map_err(|_| MissingElement("appsrc"))?;
 
         // let info = gstreamer_audio::AudioInfo::new(gstreamer_audio::AUDIO_FORMAT_i32, format.width as u32, format.height as u32)
         //     .fps(format.frame_rate_gst_fraction)
         //     .build()
         //     .expect("Failed to create video info");
 
-        let queue = gstreamer::ElementFactory::make("queue", None).ok_or(MissingElement("queue"))?;
-        // let unalignedparse = gstreamer::ElementFactory::make("unalignedaudioparse", None).ok_or(MissingElement("unalignedaudioparse"))?;
-        // let audioconvert = gstreamer::ElementFactory::make("audioconvert", None).ok_or(MissingElement("audioconvert"))?;
-        self.pipeline.add_many(&[&src, &queue])?;
-        // self.pipeline.add_many(&[&src, &queue, &audioconvert])?;
+        let queue = gstreamer::ElementFactory::make("queue").build().map_err(|_| MissingElement("queue"))?;
+        // let unalignedparse = gstreamer::ElementFactory::make("unalignedaudioparse").build().map_err(|_| MissingElement("unalignedaudioparse"))?;
+        // let audioconvert = gstreamer::ElementFactory::make("audioconvert").build().map_err(|_| MissingElement("audioconvert"))?;
+        self.pipeline.add_many([&src, &queue])?;
+        // self.pipeline.add_many([&src, &queue, &audioconvert])?;
 
         let appsrc = src.clone()
             .dynamic_cast::<gstreamer_app::AppSrc>()
             .expect("Source element is expected to be an appsrc!");
 
-        let mut caps = FrameSource::raw_audio_caps();
-        {
-            let mut_structure = caps.get_mut().unwrap().get_mut_structure(0).unwrap();
-            println!("Mut structure: {:?}", mut_structure);
-            mut_structure.set_value("channels", audio_format.channels.to_send_value());
-            mut_structure.set_value("rate", audio_format.rate.to_send_value());
-            println!("Mut structure after: {:?}", mut_structure);
-        }
-        appsrc.set_caps(&caps);
-        appsrc.set_property_format(gstreamer::Format::Time);
+        appsrc.set_caps(Some(&Self::audio_caps(audio_format)));
+        appsrc.set_format(gstreamer::Format::Time);
         appsrc.set_max_bytes(1024*1024*1024);
-        appsrc.set_property_block(true);
+        appsrc.set_block(true);
 
         src.link(&queue)?;
         // queue.link(&unalignedparse)?;
         // queue.link(&audioconvert)?;
 
         match &self.sink_type {
-            &SinkType::file_mp4(_) => {
-                let faac = gstreamer::ElementFactory::make("faac", None).ok_or(MissingElement("faac"))?;
-                // midside=false tns=false bitrate=320000 shortctl=SHORTCTL_NOSHORT
-                faac.set_property("midside", &false)?;
-                faac.set_property("tns", &false)?;
-                faac.set_property_from_str("shortctl", "SHORTCTL_NOSHORT");
-                faac.set_property("bitrate", &384000i64);
-                // faac.set_property("quality", &300i32)?;
-
-                self.pipeline.add_many(&[&faac])?;
-                queue.link(&faac)?;
-                faac.link_pads("src", self.muxer.as_ref().unwrap(), "audio_0")?;
+            &SinkType::File { audio_codec, .. } => {
+                let encoder = gstreamer::ElementFactory::make(audio_codec.encoder()).build().map_err(|_| MissingElement(audio_codec.encoder()))?;
+                match audio_codec {
+                    AudioCodec::Aac => {
+                        // midside=false tns=false bitrate=320000 shortctl=SHORTCTL_NOSHORT
+                        encoder.set_property("midside", false);
+                        encoder.set_property("tns", false);
+                        encoder.set_property_from_str("shortctl", "SHORTCTL_NOSHORT");
+                        encoder.set_property("bitrate", 384000i32);
+                        // faac.set_property("quality", &300i32)?;
+                    },
+                    AudioCodec::Opus => {
+                        encoder.set_property("bitrate", 256000i32);
+                    },
+                    AudioCodec::Vorbis => {
+                        encoder.set_property("quality", 0.6f32);
+                    },
+                    AudioCodec::Flac => {}
+                }
+
+                // each encoder takes its own sample formats and rates
+                let audioconvert = gstreamer::ElementFactory::make("audioconvert").build().map_err(|_| MissingElement("audioconvert"))?;
+                let audioresample = gstreamer::ElementFactory::make("audioresample").build().map_err(|_| MissingElement("audioresample"))?;
+
+                self.pipeline.add_many([&audioconvert, &audioresample, &encoder])?;
+                gstreamer::Element::link_many([&queue, &audioconvert, &audioresample, &encoder])?;
+                encoder.link_pads(Some("src"), self.muxer.as_ref().unwrap(), Some("audio_0"))?;
+            },
+            &SinkType::Lossless { ref uri, format: lossless } => {
+                let encoder = gstreamer::ElementFactory::make(lossless.audio_encoder()).build().map_err(|_| MissingElement(lossless.audio_encoder()))?;
+                let audioconvert = gstreamer::ElementFactory::make("audioconvert").build().map_err(|_| MissingElement("audioconvert"))?;
+
+                self.pipeline.add_many([&audioconvert, &encoder])?;
+                gstreamer::Element::link_many([&queue, &audioconvert, &encoder])?;
+                self.link_encoder(&encoder, "audio_0", &LosslessFormat::audio_path(uri))?;
+            },
+            &SinkType::ImageSequence { ref pattern, .. } => {
+                let audioconvert = gstreamer::ElementFactory::make("audioconvert").build().map_err(|_| MissingElement("audioconvert"))?;
+                let wavenc = gstreamer::ElementFactory::make("wavenc").build().map_err(|_| MissingElement("wavenc"))?;
+
+                self.pipeline.add_many([&audioconvert, &wavenc])?;
+                gstreamer::Element::link_many([&queue, &audioconvert, &wavenc])?;
+                self.link_encoder(&wavenc, "audio_0", &ImageFormat::audio_path(pattern))?;
             },
             &SinkType::playback => {
-                queue.link(self.audio_sink.as_ref().unwrap())?;
+                let audsink = gstreamer::ElementFactory::make("autoaudiosink").build().map_err(|_| MissingElement("autoaudiosink"))?;
+                self.pipeline.add(&audsink)?;
+                queue.link(&audsink)?;
+                self.audio_sink = Some(audsink);
             }
         };
 
@@ -1,35 +1,109 @@
-use pipeline::pipeline_utils::*;
-use audio::audio_buffer::*;
-use video::video_buffer::*;
+use crate::pipeline::pipeline_utils::*;
+use crate::audio::audio_buffer::*;
+use crate::video::video_buffer::*;
 
-use audio::audio_format::*;
-use video::video_format::*;
+use crate::audio::audio_format::*;
+use crate::video::video_format::*;
 
-use audio::audio_iter::*;
-use video::video_iter::*;
+use crate::audio::audio_iter::*;
+use crate::video::video_iter::*;
 
-use std::i32;
 use std::thread;
 use std::sync::Mutex;
 use std::sync::Arc;
 use std::sync::mpsc::Receiver;
 use std::sync::mpsc::sync_channel;
+use std::sync::mpsc::SyncSender;
 
-
-use gstreamer;
-use gstreamer_app;
-use gstreamer_audio;
-use gstreamer_video;
 use gstreamer::prelude::*;
 
+use log::{trace, debug, info, error};
+use anyhow::Error;
+
+#[derive(Debug, thiserror::Error)]
+#[error("Failed to link pad: {}", .0)]
+pub struct PadLinkFailed(pub String);
+
+#[derive(Debug, thiserror::Error)]
+#[error("Source description has no unlinked src pads to decode: {}", .0)]
+pub struct NoSourcePads(pub String);
+
+/// Where a FrameSource reads its streams from.
+pub enum SourceInput {
+    /// a local file, read with filesrc ! decodebin
+    File(String),
+    /// any URI gstreamer can open (file://, http://, ...), read with uridecodebin
+    Uri(String),
+    /// a gst-launch style description such as `videotestsrc ! video/x-raw,width=640 audiotestsrc`.
+    /// every unlinked src pad is decoded, so raw and encoded streams both work.
+    Description(String)
+}
+
+impl SourceInput {
+    /// Arguments with a scheme (`file://`, `http://`, ...) are URIs, anything else is a local path.
+    pub fn from_arg(arg: &str) -> SourceInput {
+        if arg.contains("://") {
+            SourceInput::Uri(arg.to_string())
+        } else {
+            SourceInput::File(arg.to_string())
+        }
+    }
+}
+
+/// The streams a FrameSource found in its input.
+/// Sent once all of the decoders have exposed their pads, before the first buffer.
+#[derive(Copy, Clone, Debug)]
+pub struct SourceStreams {
+    pub audio: bool,
+    pub video: bool
+}
+
+impl SourceStreams {
+    pub fn none() -> SourceStreams {
+        SourceStreams {
+            audio: false,
+            video: false
+        }
+    }
+}
 
-#[macro_use]
-use gstreamer::Element;
+/// The part of the input to output, in seconds of input time.
+#[derive(Copy, Clone, Debug)]
+pub struct TimeRange {
+    pub start: f64,
+    pub end: Option<f64>
+}
 
-use byte_slice_cast::*;
+impl TimeRange {
+    pub fn all() -> TimeRange {
+        TimeRange {
+            start: 0f64,
+            end: None
+        }
+    }
 
-extern crate failure;
-use failure::Error;
+    pub fn is_all(&self) -> bool {
+        self.start <= 0f64 && self.end.is_none()
+    }
+
+    /// Whether a frame showing from `time` for `duration` is (partly) in the range.
+    pub fn contains(&self, time: f64, duration: f64) -> bool {
+        time + duration > self.start && self.end.map(|e| time < e).unwrap_or(true)
+    }
+}
+
+/// The elements decoded pads are linked into, shared by every decoder of a FrameSource.
+#[derive(Clone)]
+struct DecoderTargets {
+    audio_entry: gstreamer::Element,
+    audio_mixed: bool,
+    audiosink_appsink: gstreamer_app::AppSink,
+    videoconvert: gstreamer::Element,
+    videosink_appsink: gstreamer_app::AppSink,
+    streams: Arc<Mutex<SourceStreams>>,
+    pending_decoders: Arc<Mutex<usize>>,
+    streams_tx: Arc<Mutex<SyncSender<SourceStreams>>>
+}
 
 pub struct FrameSource {
     pipeline: gstreamer::Pipeline,
@@ -44,17 +118,59 @@ impl IntoPipeline for FrameSource {
 }
 
 impl FrameSource {
+    /// Pauses the pipeline, and waits until every stream has its first buffer ready.
+    pub fn preroll(&self) -> Result<(), Error> {
+        self.pipeline.set_state(gstreamer::State::Paused)?;
+        self.pipeline.state(gstreamer::ClockTime::NONE).0?;
+        Ok(())
+    }
+
+    /// The length of the input in seconds, once prerolled.  None if it can't be known, e.g. for live sources.
+    pub fn duration(&self) -> Option<f64> {
+        self.pipeline.query_duration::<gstreamer::ClockTime>()
+            .map(|time| time.nseconds() as f64 / 1_000_000_000f64)
+    }
+
+    /// Prerolls the pipeline and seeks to the time, in seconds.  Call before PipelineUtils::start.
+    /// Frames are delivered from the seek position, still stamped with their time in the input.
+    pub fn seek(&self, time: f64) -> Result<(), Error> {
+        self.seek_range(time, None)
+    }
+
+    /// Like seek, and the source ends at `end` instead of at the end of the input.
+    pub fn seek_range(&self, start: f64, end: Option<f64>) -> Result<(), Error> {
+        // the seek only works once every stream has prerolled
+        self.preroll()?;
+
+        let to_clock_time = |time: f64| gstreamer::ClockTime::from_nseconds((time * 1_000_000_000f64) as u64);
+        let (stop_type, stop) = match end {
+            Some(end) => (gstreamer::SeekType::Set, Some(to_clock_time(end))),
+            None => (gstreamer::SeekType::None, gstreamer::ClockTime::NONE)
+        };
+
+        self.pipeline.seek(
+            1f64,
+            gstreamer::SeekFlags::FLUSH | gstreamer::SeekFlags::ACCURATE,
+            gstreamer::SeekType::Set,
+            Some(to_clock_time(start)),
+            stop_type,
+            stop
+        )?;
+        info!(target: "source", "Seeked to {:.3}s, until {:?}", start, end);
+        Ok(())
+    }
+
     pub fn raw_audio_caps() -> gstreamer::Caps {
-        gstreamer::Caps::new_simple(
-            "audio/x-raw",
-            &[
-                ("format", &gstreamer_audio::AUDIO_FORMAT_S16.to_string()),
-                ("layout", &"interleaved"),
-                ("channels", &gstreamer::IntRange::<i32>::new(1, i32::MAX)),
-                ("rate", &48000),
-                ("channel-mask", &gstreamer::Bitmask::new(0x0000000000000003))
-            ],
-        )
+        gstreamer::Caps::builder("audio/x-raw")
+            // float first, so float sources keep their headroom. any channel layout is accepted.
+            .field("format", gstreamer::List::new([
+                gstreamer_audio::AUDIO_FORMAT_F32.to_string(),
+                gstreamer_audio::AUDIO_FORMAT_S16.to_string()
+            ]))
+            .field("layout", "interleaved")
+            .field("channels", gstreamer::IntRange::<i32>::new(1, i32::MAX))
+            .field("rate", 48000)
+            .build()
     }
 
     // pub fn raw_audio_caps_output() -> gstreamer::Caps {
@@ -70,115 +186,192 @@ impl FrameSource {
     // }
 
     pub fn raw_video_caps() -> gstreamer::Caps {
-        gstreamer::Caps::new_simple(
-            "video/x-raw", 
-            &[
-                ("format", &"AYUV"),
-                ("interlace-mode", &"progressive"),
-                ("pixel-aspect-ratio", &gstreamer::Fraction::new(1,1)), 
-                ("chroma-site", &"mpeg2"), 
-                ("colorimetry", &"bt709")
-            ]
-        )
+        gstreamer::Caps::builder("video/x-raw")
+            .field("format", "AYUV")
+            .field("interlace-mode", "progressive")
+            .field("pixel-aspect-ratio", gstreamer::Fraction::new(1, 1))
+            .field("chroma-site", "mpeg2")
+            .field("colorimetry", "bt709")
+            .build()
     }
 
-    pub fn new(uri: &str) -> Result<(FrameSource,Receiver<Arc<Mutex<AudioBuffer>>>,Receiver<Arc<Mutex<VideoBuffer>>>), Error> {
-        let pipeline = gstreamer::Pipeline::new("recode-input");
+    pub fn new(uri: &str) -> Result<(FrameSource,Receiver<SourceStreams>,Receiver<Arc<Mutex<AudioBuffer>>>,Receiver<Arc<Mutex<VideoBuffer>>>), Error> {
+        Self::from_input(SourceInput::from_arg(uri))
+    }
 
-        let src = gstreamer::ElementFactory::make("filesrc", None).ok_or(MissingElement("filesrc"))?;
-        src.set_property("location", &uri)?;
+    pub fn from_input(input: SourceInput) -> Result<(FrameSource,Receiver<SourceStreams>,Receiver<Arc<Mutex<AudioBuffer>>>,Receiver<Arc<Mutex<VideoBuffer>>>), Error> {
+        Self::from_inputs(input, None, false)
+    }
+
+    /// Decodes video from `input`, and audio from `audio_input` if one is given.
+    /// The audio of `input` is dropped, unless `mix_audio` is set, in which case both tracks are mixed.
+    pub fn from_inputs(input: SourceInput, audio_input: Option<SourceInput>, mix_audio: bool) -> Result<(FrameSource,Receiver<SourceStreams>,Receiver<Arc<Mutex<AudioBuffer>>>,Receiver<Arc<Mutex<VideoBuffer>>>), Error> {
+        let pipeline = gstreamer::Pipeline::with_name("recode-input");
 
         let mut frameSource = FrameSource {  
             pipeline: pipeline,
             arx: None,
             vrx: None
         };
-        let (arx,vrx) = frameSource.register_appsinks(&src)?;
 
-        return Ok((frameSource, arx, vrx));
+        let mut decoders = Vec::new();
+        let main_streams = SourceStreams {
+            audio: audio_input.is_none() || mix_audio,
+            video: true
+        };
+        for decoder in frameSource.add_decoders(&input)? {
+            decoders.push((decoder, main_streams));
+        }
+
+        if let Some(ref audio_input) = audio_input {
+            let audio_streams = SourceStreams {
+                audio: true,
+                video: false
+            };
+            for decoder in frameSource.add_decoders(audio_input)? {
+                decoders.push((decoder, audio_streams));
+            }
+        }
+
+        let mix = audio_input.is_some() && mix_audio;
+        let (srx,arx,vrx) = frameSource.register_appsinks(&decoders, mix)?;
+
+        return Ok((frameSource, srx, arx, vrx));
     }
 
-    fn register_appsinks(&mut self, src: &gstreamer::Element) -> Result<(Receiver<Arc<Mutex<AudioBuffer>>>,Receiver<Arc<Mutex<VideoBuffer>>>),Error> {
-        let decodebin =
-            gstreamer::ElementFactory::make("decodebin", None).ok_or(MissingElement("decodebin"))?;
+    /// Adds the input elements to the pipeline,
+    /// and returns the decoders whose pads should be connected to the appsinks.
+    fn add_decoders(&mut self, input: &SourceInput) -> Result<Vec<gstreamer::Element>, Error> {
+        match input {
+            &SourceInput::File(ref location) => {
+                let src = gstreamer::ElementFactory::make("filesrc").build().map_err(|_| MissingElement("filesrc"))?;
+                src.set_property("location", location.as_str());
+                let decodebin =
+                    gstreamer::ElementFactory::make("decodebin").build().map_err(|_| MissingElement("decodebin"))?;
+
+                self.pipeline.add_many([&src, &decodebin])?;
+                gstreamer::Element::link_many([&src, &decodebin])?;
+                Ok(vec![decodebin])
+            },
+            &SourceInput::Uri(ref uri) => {
+                let uridecodebin =
+                    gstreamer::ElementFactory::make("uridecodebin").build().map_err(|_| MissingElement("uridecodebin"))?;
+                uridecodebin.set_property("uri", uri.as_str());
+
+                self.pipeline.add_many([&uridecodebin])?;
+                Ok(vec![uridecodebin])
+            },
+            &SourceInput::Description(ref description) => {
+                // ghost the unlinked pads, so each chain in the description shows up as a src pad on the bin
+                let bin = gstreamer::parse::bin_from_description(description, true)?;
+                let bin = bin.upcast::<gstreamer::Element>();
+                self.pipeline.add_many([&bin])?;
+
+                let mut decoders = Vec::new();
+                for pad in bin.src_pads() {
+                    let decodebin =
+                        gstreamer::ElementFactory::make("decodebin").build().map_err(|_| MissingElement("decodebin"))?;
+                    self.pipeline.add_many([&decodebin])?;
+                    bin.link_pads(Some(pad.name().as_str()), &decodebin, Some("sink"))?;
+                    decoders.push(decodebin);
+                }
+
+                // without a decoder, no-more-pads never fires and the streams are never reported
+                if decoders.is_empty() {
+                    Err(NoSourcePads(description.clone()))?;
+                }
+
+                Ok(decoders)
+            }
+        }
+    }
 
-        let audiosink = gstreamer::ElementFactory::make("appsink", None).unwrap();
-        let videosink = gstreamer::ElementFactory::make("appsink", None).unwrap();
+    fn register_appsinks(&mut self, decoders: &[(gstreamer::Element, SourceStreams)], mix: bool) -> Result<(Receiver<SourceStreams>,Receiver<Arc<Mutex<AudioBuffer>>>,Receiver<Arc<Mutex<VideoBuffer>>>),Error> {
+        let audiosink = gstreamer::ElementFactory::make("appsink").build().map_err(|_| MissingElement("appsink"))?;
+        let videosink = gstreamer::ElementFactory::make("appsink").build().map_err(|_| MissingElement("appsink"))?;
 
-        let videoconvert = gstreamer::ElementFactory::make("videoconvert", None).unwrap();
-        let audioconvert = gstreamer::ElementFactory::make("audioconvert", None).unwrap();
-        let audioresample = gstreamer::ElementFactory::make("audioresample", None).unwrap();
+        let videoconvert = gstreamer::ElementFactory::make("videoconvert").build().map_err(|_| MissingElement("videoconvert"))?;
+        let audioconvert = gstreamer::ElementFactory::make("audioconvert").build().map_err(|_| MissingElement("audioconvert"))?;
+        let audioresample = gstreamer::ElementFactory::make("audioresample").build().map_err(|_| MissingElement("audioresample"))?;
 
         let audiosink_appsink = 
             audiosink
             .dynamic_cast::<gstreamer_app::AppSink>()
             .expect("Sink element is expected to be an appsink!");
 
-        audiosink_appsink.set_caps(&Self::raw_audio_caps());
+        audiosink_appsink.set_caps(Some(&Self::raw_audio_caps()));
 
         let videosink_appsink = 
             videosink
             .dynamic_cast::<gstreamer_app::AppSink>()
             .expect("Sink element is expected to be an appsink!");
 
-        videosink_appsink.set_caps(&Self::raw_video_caps());
+        videosink_appsink.set_caps(Some(&Self::raw_video_caps()));
 
-        let video_format = Arc::new(Mutex::new(VideoFormat::empty()));
-        let audio_format = Arc::new(Mutex::new(AudioFormat::empty()));
-        let vf1 = video_format.clone();
-        let af1 = audio_format.clone();
+        // the last negotiated formats.  Both callbacks read the format from the caps negotiated on
+        // the appsink, not the decoder, on every sample, and log it when the caps change mid-stream
+        let vf1 = Mutex::new(VideoFormat::empty());
+        let af1 = Mutex::new(AudioFormat::empty());
 
         let (vtx, vrx) = sync_channel(8);
         let vtx_mutex = Mutex::new(vtx);
         videosink_appsink.set_callbacks(
-            gstreamer_app::AppSinkCallbacks::new()
+            gstreamer_app::AppSinkCallbacks::builder()
                 .new_sample(move |appsink| {
-                    let sample = match appsink.pull_sample() {
-                        None => return gstreamer::FlowReturn::Eos,
-                        Some(sample) => sample,
-                    };
+                    let sample = appsink.pull_sample().map_err(|_| gstreamer::FlowError::Eos)?;
 
-                    let buffer = if let Some(buffer) = sample.get_buffer() {
+                    let buffer = if let Some(buffer) = sample.buffer() {
                         buffer
                     } else {
-                        gst_element_error!(
+                        gstreamer::element_error!(
                             appsink,
                             gstreamer::ResourceError::Failed,
                             ("Failed to get buffer from appsink")
                         );
 
-                        return gstreamer::FlowReturn::Error;
+                        return Err(gstreamer::FlowError::Error);
                     };
 
-                    let samples = if let Some(map) = buffer.map_readable() {
+                    let samples = if let Ok(map) = buffer.map_readable() {
                         map
                     } else {
-                        gst_element_error!(
+                        gstreamer::element_error!(
                             appsink,
                             gstreamer::ResourceError::Failed,
                             ("Failed to map buffer readable")
                         );
 
-                        return gstreamer::FlowReturn::Error;
+                        return Err(gstreamer::FlowError::Error);
                     };
 
-                    // let caps = sample.get_caps().unwrap();
-                    // let caps_structure = caps.get_structure(0).unwrap();
-                    // let framerate = caps_structure.get::<gst::Fraction>("framerate").unwrap();
-                    // let width = caps_structure.get::<i32>("width").unwrap();
-                    // let height = caps_structure.get::<i32>("height").unwrap();
-                    
-                    // println!("Caps structure : {:?}",caps_structure);
-                    // println!("Got video sample with framerate: {:?}", fps);
+                    // AYUV at the decoded size and frame rate
+                    let format = if let Some(format) = sample.caps().and_then(VideoFormat::from_caps) {
+                        format
+                    } else {
+                        gstreamer::element_error!(
+                            appsink,
+                            gstreamer::ResourceError::Failed,
+                            ("Failed to read video format from caps")
+                        );
+
+                        return Err(gstreamer::FlowError::Error);
+                    };
 
+                    {
+                        let mut last_format = vf1.lock().unwrap();
+                        if *last_format != format {
+                            info!(target: "source", "Video format: {:?}", format);
+                            *last_format = format;
+                        }
+                    }
 
                     // TODO: extract into new
                     let video_buffer = VideoBuffer {
                         buffer: samples.to_vec().clone(),
-                        format: vf1.lock().unwrap().clone(),
-                        time: buffer.get_pts().nanoseconds().unwrap_or(0u64) as f64 / 1_000_000_000f64,
-                        clock_time: buffer.get_pts(),
-                        duration: buffer.get_duration()
+                        format: format,
+                        time: buffer.pts().map(|e| e.nseconds()).unwrap_or(0u64) as f64 / 1_000_000_000f64,
+                        clock_time: buffer.pts().unwrap_or(gstreamer::ClockTime::ZERO),
+                        duration: buffer.duration().unwrap_or(gstreamer::ClockTime::ZERO)
                         // framerate: framerate,
                         // width: width,
                         // height: height
@@ -186,9 +379,12 @@ impl FrameSource {
 
                     // println!("Captured video buffer at time {:?}", video_buffer.time);
 
-                    vtx_mutex.lock().unwrap().send(Arc::new(Mutex::new(video_buffer)));
+                    // a consumer that hung up doesn't want any more frames
+                    if vtx_mutex.lock().unwrap().send(Arc::new(Mutex::new(video_buffer))).is_err() {
+                        return Err(gstreamer::FlowError::Eos);
+                    }
                     
-                    gstreamer::FlowReturn::Ok
+                    Ok(gstreamer::FlowSuccess::Ok)
                 })
                 .build()
         );
@@ -196,150 +392,264 @@ impl FrameSource {
         let (atx, arx) = sync_channel(8);
         let atx_mutex = Mutex::new(atx);
         audiosink_appsink.set_callbacks(
-            gstreamer_app::AppSinkCallbacks::new()
+            gstreamer_app::AppSinkCallbacks::builder()
                 .new_sample(move |appsink| {
-                    let sample = match appsink.pull_sample() {
-                        None => return gstreamer::FlowReturn::Eos,
-                        Some(sample) => sample,
-                    };
+                    let sample = appsink.pull_sample().map_err(|_| gstreamer::FlowError::Eos)?;
 
-                    let buffer = if let Some(buffer) = sample.get_buffer() {
+                    let buffer = if let Some(buffer) = sample.buffer_owned() {
                         buffer
                     } else {
-                        gst_element_error!(
+                        gstreamer::element_error!(
                             appsink,
                             gstreamer::ResourceError::Failed,
                             ("Failed to get buffer from appsink")
                         );
 
-                        return gstreamer::FlowReturn::Error;
+                        return Err(gstreamer::FlowError::Error);
+                    };
+                    trace!(target: "source", "IN  {:?}", buffer.pts());
+
+                    // F32 or S16 in the decoder's channel layout
+                    let format = if let Some(format) = sample.caps().and_then(AudioFormat::from_caps) {
+                        format
+                    } else {
+                        gstreamer::element_error!(
+                            appsink,
+                            gstreamer::ResourceError::Failed,
+                            ("Failed to read audio format from caps")
+                        );
+
+                        return Err(gstreamer::FlowError::Error);
                     };
-                    println!("IN  {}", buffer.get_pts());
+
+                    {
+                        let mut last_format = af1.lock().unwrap();
+                        if *last_format != format {
+                            info!(target: "source", "Audio format: {:?}", format);
+                            *last_format = format;
+                        }
+                    }
 
                     let vec;
                     {
-                        let map = if let Some(map) = buffer.map_readable() {
+                        let map = if let Ok(map) = buffer.map_readable() {
                             map
                         } else {
-                            gst_element_error!(
+                            gstreamer::element_error!(
                                 appsink,
                                 gstreamer::ResourceError::Failed,
                                 ("Failed to map buffer readable")
                             );
 
-                            return gstreamer::FlowReturn::Error;
+                            return Err(gstreamer::FlowError::Error);
                         };
 
                         // println!("Buffer length: {}", map.len());
-                        let samples = if let Ok(samples) = map.as_slice().as_slice_of::<i16>() {
+                        vec = if let Some(samples) = AudioBuffer::decode_samples(map.as_slice(), &format) {
                             samples
                         } else {
-                            gst_element_error!(
+                            gstreamer::element_error!(
                                 appsink,
                                 gstreamer::ResourceError::Failed,
-                                ("Failed to interprete buffer as i32 PCM")
+                                ("Failed to interprete buffer as {:?} PCM", format.sample_format)
                             );
                     
-                            return gstreamer
-                            ::FlowReturn::Error;
+                            return Err(gstreamer::FlowError::Error);
                         };
-
-                        vec = samples.to_vec().clone();
                     }
                     
 
-                    // let caps = sample.get_caps().unwrap();
-                    // let caps_structure = caps.get_structure(0).unwrap();
-                    // let rate = caps_structure.get::<i32>("rate").unwrap();
-                    // let channels = caps_structure.get::<i32>("channels").unwrap();
-
                     // let segment = sample.get_segment().unwrap();
                     // let start = segment.get_start();
                     
-                    // println!("Got audio sample with rate {:?}, channels {:?}", rate, channels);
-                    
-                    let format = af1.lock().unwrap().clone();
                     let buffer = AudioBuffer::new(buffer, vec, format);
 
                     // println!("Captured audio buffer at time {:?}", buffer.time);
 
-                    atx_mutex.lock().unwrap().send(Arc::new(Mutex::new(buffer)));
+                    if atx_mutex.lock().unwrap().send(Arc::new(Mutex::new(buffer))).is_err() {
+                        return Err(gstreamer::FlowError::Eos);
+                    }
                     
-                    gstreamer::FlowReturn::Ok
+                    Ok(gstreamer::FlowSuccess::Ok)
                 })
                 .build()
         );
 
-        self.pipeline.add_many(&[src, &decodebin])?;
-        self.pipeline.add_many(&[&audioconvert, &audioresample, &videoconvert])?;
-        self.pipeline.add_many(&[&audiosink_appsink, &videosink_appsink])?;
-        gstreamer::Element::link_many(&[src, &decodebin])?;
-        
+        self.pipeline.add_many([&audioconvert, &audioresample, &videoconvert])?;
+        self.pipeline.add_many([&audiosink_appsink, &videosink_appsink])?;
+        gstreamer::Element::link_many([&audioconvert, &audioresample, audiosink_appsink.upcast_ref()])?;
+        gstreamer::Element::link_many([&videoconvert, videosink_appsink.upcast_ref()])?;
+
+        // decoded audio pads are linked into the mixer when there is more than one audio track
+        let audio_entry = if mix {
+            let audiomixer = gstreamer::ElementFactory::make("audiomixer").build().map_err(|_| MissingElement("audiomixer"))?;
+            self.pipeline.add_many([&audiomixer])?;
+            audiomixer.link(&audioconvert)?;
+            audiomixer
+        } else {
+            audioconvert.clone()
+        };
+
+        let (stx, srx) = sync_channel(1);
+        let targets = DecoderTargets {
+            audio_entry: audio_entry,
+            audio_mixed: mix,
+            audiosink_appsink: audiosink_appsink,
+            videoconvert: videoconvert,
+            videosink_appsink: videosink_appsink,
+            streams: Arc::new(Mutex::new(SourceStreams::none())),
+            pending_decoders: Arc::new(Mutex::new(decoders.len())),
+            streams_tx: Arc::new(Mutex::new(stx))
+        };
+
+        for &(ref decoder, accept) in decoders {
+            Self::connect_decoder(decoder, accept, targets.clone());
+        }
+
+        return Ok((srx, arx, vrx));
+    }
+
+    /// Links the pads of a decoder into the appsinks as they appear.
+    /// Streams the decoder shouldn't contribute (per `accept`) are discarded.
+    fn connect_decoder(decoder: &gstreamer::Element, accept: SourceStreams, targets: DecoderTargets) {
+        let pad_targets = targets.clone();
+
         // hacky concurrency here.
         // I am betting that the connect pad will be available before the appsink callbacks are triggered
 
         // Need to move a new reference into the closure
-        decodebin.connect_pad_added(move |element, src_pad| {
-            let caps = src_pad.get_current_caps();
+        decoder.connect_pad_added(move |element, src_pad| {
+            let targets = &pad_targets;
+            let caps = src_pad.current_caps();
             if caps.is_none() {
                 return;
             }
 
             let caps = caps.unwrap();
             for structure in caps.iter() {
-                let name = structure.get_name();
-                println!("{:?}", structure);
-                if name.starts_with("audio/") {
-                    println!("Audio structure: {:?}", structure);
-                    let rate = structure.get::<i32>("rate").unwrap();
-                    let channels = structure.get::<i32>("channels").unwrap();
-
-                    let mut audstr = audio_format.lock().unwrap();
-                    *audstr = AudioFormat::new(rate, channels);
-
-                    match element.link(&audioconvert) {
-                        Ok(_) => println!("Connected audio pad: {}", name),
-                        Err(e) => println!("Error connecting audio pad: {}", e)
+                let name = structure.name();
+                debug!(target: "source", "{:?}", structure);
+                if name.starts_with("audio/") && !accept.audio {
+                    debug!(target: "source", "Discarding audio pad: {}", name);
+                    // posted on the bus, as the decoder stalls with not-linked without the fakesink
+                    if let Err(e) = Self::discard_pad(element, src_pad) {
+                        gstreamer::element_error!(
+                            element,
+                            gstreamer::CoreError::Pad,
+                            ("Failed to discard audio pad: {}", e)
+                        );
                     }
+                } else if name.starts_with("audio/") {
+                    debug!(target: "source", "Audio structure: {:?}", structure);
+                    let linked = if targets.audio_mixed {
+                        Self::link_mixer_branch(element, src_pad, &targets.audio_entry)
+                    } else {
+                        Self::link_pad(src_pad, &targets.audio_entry)
+                    };
 
-                    match audioconvert.link(&audioresample) {
-                        Ok(_) => println!("Connected audio pad: {}", name),
-                        Err(e) => println!("Error connecting audio pad: {}", e)
+                    match linked {
+                        Ok(_) => debug!(target: "source", "Connected audio pad: {}", name),
+                        Err(e) => error!(target: "source", "Error connecting audio pad: {}", e)
                     }
 
-                    match audioresample.link(&audiosink_appsink) {
-                        Ok(_) => println!("Connected audio conversion to audio sink"),
-                        Err(e) => println!("Error connecting audio conversion: {}", e)
+                    targets.streams.lock().unwrap().audio = true;
+                }
+                
+                if name.starts_with("video/") && !accept.video {
+                    debug!(target: "source", "Discarding video pad: {}", name);
+                    // posted on the bus, as the decoder stalls with not-linked without the fakesink
+                    if let Err(e) = Self::discard_pad(element, src_pad) {
+                        gstreamer::element_error!(
+                            element,
+                            gstreamer::CoreError::Pad,
+                            ("Failed to discard video pad: {}", e)
+                        );
+                    }
+                } else if name.starts_with("video/") {
+                    match Self::link_pad(src_pad, &targets.videoconvert) {
+                        Ok(_) => debug!(target: "source", "Connected video pad: {}", name),
+                        Err(e) => error!(target: "source", "Error connecting video pad: {}", e)
                     }
 
-                    audioconvert.sync_state_with_parent();
-                    audioresample.sync_state_with_parent();
+                    targets.streams.lock().unwrap().video = true;
                 }
-                
-                if name.starts_with("video/") {
-                    let framerate = structure.get::<gstreamer::Fraction>("framerate").unwrap();
-                    let width = structure.get::<i32>("width").unwrap();
-                    let height = structure.get::<i32>("height").unwrap();
+            }
+        });
 
-                    let mut videostr = video_format.lock().unwrap();
-                    *videostr = VideoFormat::new(framerate, width, height);
+        decoder.connect_no_more_pads(move |_| {
+            let mut pending = targets.pending_decoders.lock().unwrap();
+            *pending -= 1;
+            if *pending > 0 {
+                return;
+            }
 
-                    match element.link(&videoconvert) {
-                        Ok(_) =>  println!("Connected video pad: {}", name),
-                        Err(e) => println!("Error connecting video pad: {}", e)
-                    }
+            let streams = *targets.streams.lock().unwrap();
+            debug!(target: "source", "Found streams: {:?}", streams);
 
-                    match videoconvert.link(&videosink_appsink) {
-                        Ok(_) => println!("Connected video conversion to video sink"),
-                        Err(e) => println!("Error connecting video conversion: {}", e)
-                    }
-                    
-                    videoconvert.sync_state_with_parent();
+            // an appsink without input would never preroll, and the pipeline would never start
+            if !streams.audio {
+                if let Err(e) = Self::remove_unused_appsink(&targets.audiosink_appsink) {
+                    error!(target: "source", "Error removing audio appsink: {}", e);
+                }
+            }
+            if !streams.video {
+                if let Err(e) = Self::remove_unused_appsink(&targets.videosink_appsink) {
+                    error!(target: "source", "Error removing video appsink: {}", e);
                 }
             }
+
+            if let Err(e) = targets.streams_tx.lock().unwrap().send(streams) {
+                error!(target: "source", "Error reporting source streams: {}", e);
+            }
         });
+    }
+
+    fn link_pad(src_pad: &gstreamer::Pad, sink: &gstreamer::Element) -> Result<(), Error> {
+        let sink_pad = sink.static_pad("sink").ok_or(MissingElement("sink pad"))?;
+        src_pad.link(&sink_pad).map_err(|e| PadLinkFailed(format!("{:?}", e)))?;
+        Ok(())
+    }
+
+    /// Converts and resamples a decoded audio pad into a new mixer input.
+    fn link_mixer_branch(decoder: &gstreamer::Element, src_pad: &gstreamer::Pad, mixer: &gstreamer::Element) -> Result<(), Error> {
+        let audioconvert = gstreamer::ElementFactory::make("audioconvert").build().map_err(|_| MissingElement("audioconvert"))?;
+        let audioresample = gstreamer::ElementFactory::make("audioresample").build().map_err(|_| MissingElement("audioresample"))?;
+
+        let bin = decoder.parent()
+            .and_then(|p| p.downcast::<gstreamer::Bin>().ok())
+            .ok_or(MissingElement("source pipeline"))?;
+        bin.add_many([&audioconvert, &audioresample])?;
+        gstreamer::Element::link_many([&audioconvert, &audioresample, mixer])?;
+        audioconvert.sync_state_with_parent()?;
+        audioresample.sync_state_with_parent()?;
+
+        Self::link_pad(src_pad, &audioconvert)
+    }
+
+    /// Sends a pad we don't want into a fakesink, so its decoder doesn't stop with not-linked.
+    fn discard_pad(decoder: &gstreamer::Element, src_pad: &gstreamer::Pad) -> Result<(), Error> {
+        let fakesink = gstreamer::ElementFactory::make("fakesink").build().map_err(|_| MissingElement("fakesink"))?;
+        fakesink.set_property("async", false);
+
+        let bin = decoder.parent()
+            .and_then(|p| p.downcast::<gstreamer::Bin>().ok())
+            .ok_or(MissingElement("source pipeline"))?;
+        bin.add(&fakesink)?;
+        fakesink.sync_state_with_parent()?;
+
+        Self::link_pad(src_pad, &fakesink)
+    }
 
-        return Ok((arx, vrx));
+    /// Takes an appsink without a stream out of the pipeline.
+    /// Ending it with EOS instead would not last, as the flushing seek of seek_range clears the EOS.
+    fn remove_unused_appsink(appsink: &gstreamer_app::AppSink) -> Result<(), Error> {
+        let bin = appsink.parent()
+            .and_then(|p| p.downcast::<gstreamer::Bin>().ok())
+            .ok_or(MissingElement("source pipeline"))?;
+        appsink.set_locked_state(true);
+        appsink.set_state(gstreamer::State::Null)?;
+        bin.remove(appsink)?;
+        Ok(())
     }
 
     fn handle_video_frame(&self, timecode: i32, frame: Vec<u8>) {
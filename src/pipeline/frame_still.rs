@@ -0,0 +1,113 @@
+use crate::pipeline::cancel::*;
+use crate::pipeline::frame_source::*;
+use crate::pipeline::frame_transform::*;
+
+use crate::audio::audio_buffer::*;
+use crate::video::video_buffer::*;
+
+use crate::audio::audio_iter::*;
+use crate::video::video_iter::*;
+
+use std::thread;
+use std::sync::Mutex;
+use std::sync::Arc;
+use std::sync::mpsc::{sync_channel, Receiver};
+
+use log::info;
+use anyhow::Error;
+
+#[derive(Debug, thiserror::Error)]
+#[error("No video frame at {:.3}s", .0)]
+pub struct NoStillFrame(pub f64);
+
+pub struct FrameStill {
+
+}
+
+impl FrameStill {
+    /// Runs the transform over the audio up to `at`, then over the video frame showing at `at`.
+    /// Earlier video frames are skipped, so pre-roll only warms up the audio measures.
+    /// Only the processed frame is sent on, as a video-only source for a FrameSink to write.
+    /// The thread fails if the input ends before `at`.
+    pub fn spawn<T: FrameTransform + Send + 'static>(transform: T, at: f64, srx: Receiver<SourceStreams>,
+    arx: Receiver<Arc<Mutex<AudioBuffer>>>, vrx: Receiver<Arc<Mutex<VideoBuffer>>>)
+    -> (::std::thread::JoinHandle<Result<(), Error>>, Receiver<SourceStreams>, Receiver<Arc<Mutex<AudioBuffer>>>, Receiver<Arc<Mutex<VideoBuffer>>>) {
+        let mut transform = transform;
+        let (stx, still_srx) = sync_channel(1);
+        let (atx, still_arx) = sync_channel::<Arc<Mutex<AudioBuffer>>>(1);
+        let (vtx, still_vrx) = sync_channel(1);
+
+        let join = thread::spawn(move || {
+            // no audio is passed on, the sink never needs the sender
+            drop(atx);
+
+            let streams = srx.recv().unwrap_or(SourceStreams::none());
+            info!(target: "sink", "Got source streams {:?}", streams);
+            stx.send(SourceStreams { audio: false, video: streams.video }).ok();
+
+            let mut audio_iter = AudioIter::new(arx);
+            let mut video_iter = VideoIter::new(vrx);
+
+            let mut has_audio_frame = streams.audio;
+            let mut has_video_frame = streams.video;
+
+            let mut atime = if has_audio_frame { 0f64 } else { f64::MAX };
+            let mut vtime = 0f64;
+            let mut found = false;
+
+            while has_video_frame && !found && !Cancel::is_cancelled() {
+                if atime < vtime {
+                    match audio_iter.next_audio_block() {
+                        Some(mut block) => {
+                            transform.process_audio_block(&mut block);
+                            atime = block.end_time();
+                        },
+                        None => {
+                            has_audio_frame = false;
+                            atime = f64::MAX;
+                        }
+                    }
+                } else {
+                    match video_iter.next_video_frame() {
+                        Some(mut frame) => {
+                            vtime = frame.time;
+                            if frame.time + frame.format.frame_duration > at {
+                                transform.process_video_frame(&mut frame, vtime);
+                                found = true;
+                            }
+                        },
+                        None => {
+                            has_video_frame = false;
+                        }
+                    }
+                }
+
+                if found {
+                    video_iter.finish();
+                }
+
+                // the processed frame is the last buffer to finish
+                let mut last = None;
+                while let Some(buf) = video_iter.next_finished_buffer() {
+                    last = Some(buf);
+                }
+                while let Some(_) = audio_iter.next_finished_buffer() {}
+
+                if found {
+                    if let Some(buf) = last {
+                        vtx.send(Arc::new(Mutex::new(buf))).ok();
+                    }
+                }
+            }
+
+            if !found {
+                Err(NoStillFrame(at))?;
+            }
+
+            info!(target: "sink", "Rendered still at {:.3}s", vtime);
+            Ok(())
+        });
+
+        (join, still_srx, still_arx, still_vrx)
+    }
+}
@@ -0,0 +1,409 @@
+use crate::pipeline::cancel::*;
+use crate::pipeline::frame_source::*;
+use crate::pipeline::frame_transform::*;
+use crate::pipeline::measures::*;
+
+use crate::audio::audio_block::*;
+use crate::audio::audio_buffer::*;
+use crate::video::video_buffer::*;
+
+use crate::audio::audio_frame::*;
+use crate::video::video_frame::*;
+
+use crate::audio::audio_iter::*;
+use crate::video::video_iter::*;
+
+use crate::measures::*;
+
+use std::io::Write;
+use std::thread;
+use std::sync::Mutex;
+use std::sync::Arc;
+use std::sync::mpsc::Receiver;
+
+
+use log::{debug, info, error};
+use serde::Serialize;
+use anyhow::Error;
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::audio::audio_format::*;
+
+    /// Collects what the trace writes, so it can be read after the trace is finished.
+    #[derive(Clone)]
+    struct SharedOutput(Arc<Mutex<Vec<u8>>>);
+
+    impl Write for SharedOutput {
+        fn write(&mut self, buf: &[u8]) -> ::std::io::Result<usize> {
+            self.0.lock().unwrap().write(buf)
+        }
+
+        fn flush(&mut self) -> ::std::io::Result<()> {
+            Ok(())
+        }
+    }
+
+    #[test]
+    pub fn test_audio_only_trace_has_rows() {
+        let output = SharedOutput(Arc::new(Mutex::new(Vec::new())));
+        let mut trace = MeasureTrace::new(TraceMeasure::AudioVolume, TraceFormat::Csv, Box::new(output.clone()));
+
+        // 4 frames per second, one block per second
+        let format = AudioFormat::new(4, 2);
+        for i in 0..3 {
+            let mut data = vec![0.5f32; 8];
+            trace.process_audio_block(&mut AudioBlock::new(&mut data, &format, i as f64));
+        }
+        trace.finish().unwrap();
+
+        let written = String::from_utf8(output.0.lock().unwrap().clone()).unwrap();
+        let lines: Vec<&str> = written.lines().collect();
+        assert_eq!(4, lines.len());
+        assert_eq!("time,volume", lines[0]);
+        assert!(lines[1].starts_with("1.000000,"));
+        assert!(lines[3].starts_with("3.000000,"));
+    }
+}
+
+#[derive(Debug, thiserror::Error)]
+#[error("Unknown measure {}, expected one of: edge, volume, fft, theta_r, r", .0)]
+pub struct UnknownMeasure(pub String);
+
+#[derive(Debug, thiserror::Error)]
+#[error("Unknown trace format {}, expected csv or json", .0)]
+pub struct UnknownTraceFormat(pub String);
+
+#[derive(Copy, Clone, Debug)]
+pub enum TraceMeasure {
+    AudioEdge,
+    AudioVolume,
+    FFT,
+    ThetaR,
+    R
+}
+
+impl TraceMeasure {
+    pub fn from_name(name: &str) -> Result<TraceMeasure, Error> {
+        match name {
+            "edge" => Ok(TraceMeasure::AudioEdge),
+            "volume" => Ok(TraceMeasure::AudioVolume),
+            "fft" => Ok(TraceMeasure::FFT),
+            "theta_r" => Ok(TraceMeasure::ThetaR),
+            "r" => Ok(TraceMeasure::R),
+            _ => Err(UnknownMeasure(name.to_string()))?
+        }
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            &TraceMeasure::AudioEdge => "edge",
+            &TraceMeasure::AudioVolume => "volume",
+            &TraceMeasure::FFT => "fft",
+            &TraceMeasure::ThetaR => "theta_r",
+            &TraceMeasure::R => "r"
+        }
+    }
+}
+
+#[derive(Copy, Clone, Debug)]
+pub enum TraceFormat {
+    Csv,
+    Json
+}
+
+impl TraceFormat {
+    pub fn from_name(name: &str) -> Result<TraceFormat, Error> {
+        match name {
+            "csv" => Ok(TraceFormat::Csv),
+            "json" => Ok(TraceFormat::Json),
+            _ => Err(UnknownTraceFormat(name.to_string()))?
+        }
+    }
+}
+
+#[derive(Serialize)]
+struct TraceRecord<'a> {
+    time: f64,
+    measure: &'static str,
+    values: &'a [f64]
+}
+
+/// A FrameTransform that records a measure instead of painting frames.
+/// Audio measures are fed exactly as FrameTransformImpl feeds them,
+/// and sampled once per video frame, which is when the look reads them.
+/// Until a video frame is seen, e.g. for an audio-only input, they are sampled after every audio block instead.
+pub struct MeasureTrace {
+    measure: TraceMeasure,
+    format: TraceFormat,
+    output: Box<dyn Write + Send>,
+    rows: usize,
+    finished: bool,
+    error: Option<Error>,
+    audio_edge: Option<NormalizedAudioEdgeMeasure>,
+    audio_volume: Option<NormalizedAudioVolumeMeasure>,
+    fft: Option<FFTMeasure>,
+    hue_center: Option<HueCenterMeasure>,
+    seen_video: bool
+}
+
+impl MeasureTrace {
+    pub fn new(measure: TraceMeasure, format: TraceFormat, output: Box<dyn Write + Send>) -> MeasureTrace {
+        MeasureTrace {
+            measure: measure,
+            format: format,
+            output: output,
+            rows: 0,
+            finished: false,
+            error: None,
+            audio_edge: None,
+            audio_volume: None,
+            fft: None,
+            hue_center: None,
+            seen_video: false
+        }
+    }
+
+    fn sample(&mut self) -> Option<Vec<f64>> {
+        match self.measure {
+            TraceMeasure::AudioEdge => self.audio_edge.as_mut().map(|e| vec![e.value(())]),
+            TraceMeasure::AudioVolume => self.audio_volume.as_mut().map(|e| vec![e.value(())]),
+            TraceMeasure::FFT => self.fft.as_mut().map(|e| e.value()),
+            TraceMeasure::ThetaR => self.hue_center.as_mut().map(|e| vec![e.value().0]),
+            TraceMeasure::R => self.hue_center.as_mut().map(|e| vec![e.value().1])
+        }
+    }
+
+    /// Writes the current value of the measure as a row at the given time.
+    fn write_sample(&mut self, time: f64) {
+        // once a write fails, e.g. on a closed pipe, the rest of the trace is dropped
+        if self.error.is_some() {
+            return;
+        }
+
+        if let Some(values) = self.sample() {
+            if let Err(e) = self.write_row(time, &values) {
+                self.error = Some(e);
+            }
+        }
+    }
+
+    fn write_header(&mut self, columns: usize) -> Result<(), Error> {
+        match self.format {
+            TraceFormat::Csv => {
+                let mut header = "time".to_string();
+                if columns == 1 {
+                    header.push_str(&format!(",{}", self.measure.name()));
+                } else {
+                    for i in 0..columns {
+                        header.push_str(&format!(",{}_{}", self.measure.name(), i));
+                    }
+                }
+                writeln!(self.output, "{}", header)?;
+            },
+            TraceFormat::Json => {
+                writeln!(self.output, "[")?;
+            }
+        }
+
+        Ok(())
+    }
+
+    fn write_row(&mut self, time: f64, values: &[f64]) -> Result<(), Error> {
+        if self.rows == 0 {
+            self.write_header(values.len())?;
+        }
+
+        match self.format {
+            TraceFormat::Csv => {
+                let mut row = format!("{:.6}", time);
+                for v in values {
+                    row.push_str(&format!(",{}", v));
+                }
+                writeln!(self.output, "{}", row)?;
+            },
+            TraceFormat::Json => {
+                if self.rows > 0 {
+                    writeln!(self.output, ",")?;
+                }
+
+                let record = TraceRecord {
+                    time: time,
+                    measure: self.measure.name(),
+                    values: values
+                };
+                write!(self.output, "  {}", serde_json::to_string(&record)?)?;
+            }
+        }
+
+        self.rows += 1;
+        Ok(())
+    }
+
+    fn write_footer(&mut self) -> Result<(), Error> {
+        if let TraceFormat::Json = self.format {
+            let close = if self.rows == 0 { "[]" } else { "\n]" };
+            writeln!(self.output, "{}", close)?;
+        }
+
+        self.output.flush()?;
+        Ok(())
+    }
+
+    /// Closes the trace output, returning the first error from writing it.
+    pub fn finish(mut self) -> Result<(), Error> {
+        self.finished = true;
+        if let Some(e) = self.error.take() {
+            return Err(e);
+        }
+        self.write_footer()
+    }
+}
+
+impl Drop for MeasureTrace {
+    fn drop(&mut self) {
+        if self.finished {
+            return;
+        }
+
+        if let Err(e) = self.write_footer() {
+            error!(target: "sink", "Error finishing trace: {}", e);
+        }
+    }
+}
+
+impl FrameTransform for MeasureTrace {
+    fn process_audio_frame(&mut self, aframe: &mut AudioFrame, atime: f64) {
+        // silent frames are skipped, just like FrameTransformImpl does
+        if aframe.is_silent() {
+            return;
+        }
+
+        match self.measure {
+            TraceMeasure::AudioEdge => {
+                if self.audio_edge.is_none() {
+                    self.audio_edge = Some(NormalizedAudioEdgeMeasure::new(&aframe.format));
+                }
+                self.audio_edge.as_mut().unwrap().update(aframe);
+            },
+            TraceMeasure::AudioVolume => {
+                if self.audio_volume.is_none() {
+                    self.audio_volume = Some(NormalizedAudioVolumeMeasure::new(&aframe.format));
+                }
+                self.audio_volume.as_mut().unwrap().update(aframe);
+            },
+            TraceMeasure::FFT => {
+                if self.fft.is_none() {
+                    self.fft = Some(FFTMeasure::new(&aframe.format, 256));
+                }
+                self.fft.as_mut().unwrap().update(aframe);
+            },
+            TraceMeasure::ThetaR | TraceMeasure::R => {}
+        }
+    }
+
+    fn process_audio_block(&mut self, block: &mut AudioBlock) {
+        match self.measure {
+            TraceMeasure::AudioEdge => {
+                if self.audio_edge.is_none() {
+                    self.audio_edge = Some(NormalizedAudioEdgeMeasure::new(&block.format));
+                }
+                self.audio_edge.as_mut().unwrap().update_block(block);
+            },
+            TraceMeasure::AudioVolume => {
+                if self.audio_volume.is_none() {
+                    self.audio_volume = Some(NormalizedAudioVolumeMeasure::new(&block.format));
+                }
+                self.audio_volume.as_mut().unwrap().update_block(block);
+            },
+            TraceMeasure::FFT => {
+                if self.fft.is_none() {
+                    self.fft = Some(FFTMeasure::new(&block.format, 256));
+                }
+                self.fft.as_mut().unwrap().update_block(block);
+            },
+            TraceMeasure::ThetaR | TraceMeasure::R => {}
+        }
+
+        if !self.seen_video {
+            self.write_sample(block.end_time());
+        }
+    }
+
+    fn process_video_frame(&mut self, vframe: &mut VideoFrame, _vtime: f64) {
+        self.seen_video = true;
+
+        match self.measure {
+            TraceMeasure::ThetaR | TraceMeasure::R => {
+                if self.hue_center.is_none() {
+                    self.hue_center = Some(HueCenterMeasure::new(&vframe.format));
+                }
+                self.hue_center.as_mut().unwrap().update(vframe);
+            },
+            _ => {}
+        }
+
+        self.write_sample(vframe.time);
+    }
+}
+
+pub struct FrameTrace {
+
+}
+
+impl FrameTrace {
+    /// Runs the transform over every decoded frame on a new thread, without encoding anything.
+    /// Buffers are dropped as soon as the transform is done with them.
+    /// The thread hands the transform back once the input ends.
+    pub fn spawn<T: FrameTransform + Send + 'static>(transform: T, srx: Receiver<SourceStreams>,
+    arx: Receiver<Arc<Mutex<AudioBuffer>>>, vrx: Receiver<Arc<Mutex<VideoBuffer>>>) -> ::std::thread::JoinHandle<T> {
+        let mut transform = transform;
+
+        thread::spawn(move || {
+            let streams = srx.recv().unwrap_or(SourceStreams::none());
+            info!(target: "sink", "Got source streams {:?}", streams);
+
+            let mut audio_iter = AudioIter::new(arx);
+            let mut video_iter = VideoIter::new(vrx);
+
+            let mut has_audio_frame = streams.audio;
+            let mut has_video_frame = streams.video;
+
+            let mut atime = if has_audio_frame { 0f64 } else { f64::MAX };
+            let mut vtime = if has_video_frame { 0f64 } else { f64::MAX };
+
+            while (has_video_frame || has_audio_frame) && !Cancel::is_cancelled() {
+                if atime < vtime {
+                    match audio_iter.next_audio_block() {
+                        Some(mut block) => {
+                            transform.process_audio_block(&mut block);
+                            atime = block.end_time();
+                        },
+                        None => {
+                            has_audio_frame = false;
+                            atime = f64::MAX;
+                        }
+                    }
+                } else {
+                    match video_iter.next_video_frame() {
+                        Some(mut frame) => {
+                            transform.process_video_frame(&mut frame, vtime);
+                            vtime = frame.time;
+                        },
+                        None => {
+                            has_video_frame = false;
+                            vtime = f64::MAX;
+                        }
+                    }
+                }
+
+                while let Some(_) = video_iter.next_finished_buffer() {}
+                while let Some(_) = audio_iter.next_finished_buffer() {}
+            }
+
+            debug!(target: "sink", "Finished tracing frames");
+            transform
+        })
+    }
+}
@@ -1,22 +1,25 @@
-use audio::audio_frame::*;
-use video::video_frame::*;
+use crate::audio::audio_block::*;
+use crate::audio::audio_frame::*;
+use crate::video::video_frame::*;
+use log::trace;
 
-use measures::*;
+use crate::measures::*;
 
-use pipeline::measures::*;
+use crate::pipeline::measures::*;
 
 use stats::mean;
 use stats::OnlineStats;
 
-use pipeline::queue_buf::*;
+use crate::pipeline::queue_buf::*;
+use crate::pipeline::transform_params::*;
 use std::sync::Arc;
 
 use rayon::prelude::*;
 use rayon::*;
-use rayon;
 
+#[cfg(test)]
 mod tests {
-    use FrameTransformImpl;
+    use super::*;
 
     fn new_vec() -> Vec<f64> {
         vec![0f64, 0f64, 0f64, 1f64, 1f64, 1f64, 2f64, 2f64, 2f64]
@@ -53,24 +56,46 @@ mod tests {
 pub trait FrameTransform {
     fn process_audio_frame(&mut self, aframe: &mut AudioFrame, atime: f64);
 
+    /// Receives the frames of an audio buffer at once.  This is how the sinks deliver audio.
+    /// By default, each frame is handed to process_audio_frame.
+    fn process_audio_block(&mut self, block: &mut AudioBlock) {
+        for mut frame in block.frames() {
+            let atime = frame.time;
+            self.process_audio_frame(&mut frame, atime);
+        }
+    }
+
     fn process_video_frame(&mut self, vframe: &mut VideoFrame, vtime: f64);
 }
 
+impl<T: FrameTransform + ?Sized> FrameTransform for Box<T> {
+    fn process_audio_frame(&mut self, aframe: &mut AudioFrame, atime: f64) {
+        (**self).process_audio_frame(aframe, atime);
+    }
+
+    fn process_audio_block(&mut self, block: &mut AudioBlock) {
+        (**self).process_audio_block(block);
+    }
+
+    fn process_video_frame(&mut self, vframe: &mut VideoFrame, vtime: f64) {
+        (**self).process_video_frame(vframe, vtime);
+    }
+}
+
 const AUDIO_SIZE: usize = 1000;
 const BASELINE_SIZE: usize = 20000;
 const DISTURB_SIZE: usize = 3;
 const ABS_VOL_SIZE: usize = 30*3;
-const ROTATION_RATE: f64 = (1f64 / 10f64);
 
 pub struct FrameTransformImpl {
     frame_counter: usize,
     audio_edge: Option<NormalizedAudioEdgeMeasure>,
     audio_volume: Option<NormalizedAudioVolumeMeasure>,
-    theta_r_buf: Option<QueueBuf<f64>>,
-    r_buf: Option<QueueBuf<f64>>,
+    hue_center: Option<HueCenterMeasure>,
     fft: Option<FFTMeasure>,
     fft_map_cache: Option<Vec<Option<PixelMap>>>,
     angle: f64,
+    params: TransformParams,
     premap: Premap
 }
 
@@ -83,13 +108,13 @@ struct Premap {
 
 impl Premap {
 
-    pub fn new() -> Premap {
+    pub fn new(params: &TransformParams) -> Premap {
         let magnitude = Self::calculate_magnitudes();
         Premap {
             theta: Self::calculate_theta(),
-            grayscale: Self::calculate_grayscale(&magnitude),
+            grayscale: Self::calculate_grayscale(&magnitude, params),
             magnitude: magnitude,
-            y: Self::calculate_y()
+            y: Self::calculate_y(params)
         }
     }
 
@@ -111,37 +136,36 @@ impl Premap {
         }).collect()
     }
 
-    fn calculate_grayscale(magnitude_premap: &Vec<f64>) -> Vec<f64> {
+    fn calculate_grayscale(magnitude_premap: &Vec<f64>, params: &TransformParams) -> Vec<f64> {
         (0..65536).map(|e| {
-            let zero_point = 100.0;
+            let zero_point = params.gray_zero_point;
             let grayval = (128.0 - zero_point - magnitude_premap[e]).max(0.0) / (128.0 - zero_point);
-            grayval.powf(0.35)
+            grayval.powf(params.gray_exponent)
         }).collect()
     }
     
-    fn calculate_y() -> Vec<f64> {
+    fn calculate_y(params: &TransformParams) -> Vec<f64> {
         (0..256).map(|y: usize| {
             // we remap the luminosity to increase the overall lightness of the image
-            // this should be user input, not hardcoded
             // need to look into how video editors handle color maps...
-            let y = FrameTransformImpl::sigmoid_remap(y as u8, 72f64);
+            let y = FrameTransformImpl::sigmoid_remap(y as u8, params.luma_sigmoid_div);
             FrameTransformImpl::to_uf64(y)
         }).collect()
     }
 }
 
 impl FrameTransformImpl {
-    pub fn new() -> FrameTransformImpl{ 
+    pub fn new(params: TransformParams) -> FrameTransformImpl{ 
         FrameTransformImpl {
             frame_counter: 0,
             audio_edge: None,
             audio_volume: None,
             fft: None,
             fft_map_cache: None,
-            theta_r_buf: None,
-            r_buf: None,
+            hue_center: None,
             angle: 0f64,
-            premap: Premap::new()
+            premap: Premap::new(&params),
+            params: params
         }
     }
 
@@ -380,10 +404,8 @@ impl FrameTransformImpl {
     }
 
     fn init(&mut self, vframe: &VideoFrame) {
-        if self.theta_r_buf.is_none() {
-            let buf_size = vframe.format.frames_in(10.0);
-            self.theta_r_buf = Some(QueueBuf::new(vec![0f64; buf_size]));
-            self.r_buf = Some(QueueBuf::new(vec![1f64; buf_size]));
+        if self.hue_center.is_none() {
+            self.hue_center = Some(HueCenterMeasure::new(&vframe.format));
         }
 
         // let mut fft_output = vec!(Complex64::zero(); FFT_SIZE);
@@ -415,38 +437,10 @@ impl FrameTransformImpl {
     }
 
     fn calculate_theta_r(&mut self, vframe: &VideoFrame) -> (f64, f64) {
-        let mut u_sum = 0f64;
-        let mut v_sum = 0f64;
-        let mut n_sum = 0usize;
-        let scan_pixels = 257;
-        for pixel in vframe.data.chunks(4 * scan_pixels) {
-            let u = pixel[2];
-            let v = pixel[3];
-            let uv_idx = u as usize * 256 + v as usize;
-            let theta = self.premap.theta[uv_idx];
-
-            u_sum += theta.cos();
-            v_sum += theta.sin();
-            n_sum += 1;
-        }
-
-        let mut theta_r = v_sum.atan2(u_sum);
-        while theta_r >= 2.0 * ::std::f64::consts::PI {
-            theta_r -= 2.0 * ::std::f64::consts::PI;
-        }
-        while theta_r < 0.0 {
-            theta_r += 2.0 * ::std::f64::consts::PI;
-        }
-        let sin_v = v_sum / n_sum as f64;
-        let cos_u = u_sum / n_sum as f64;
-        // println!("v_sum: {}, u_sum: {}, sin_v: {}, cos_u: {}", v_sum, u_sum, sin_v, cos_u);
-        let r = (-(sin_v*sin_v + cos_u*cos_u).ln()).sqrt();
-        println!("Theta_r: {:.2}, r: {:.2}", theta_r, r);
-        self.theta_r_buf.as_mut().unwrap().push(theta_r);
-        self.r_buf.as_mut().unwrap().push(r);
-        let theta_r = mean(self.theta_r_buf.as_ref().unwrap().extract().iter().map(|e| *e));
-        let r = mean(self.r_buf.as_ref().unwrap().extract().iter().map(|e| *e));
-        println!("Avg theta-r: {:.2}, avg r: {:.2}", theta_r, r);
+        let hue_center = self.hue_center.as_mut().unwrap();
+        hue_center.update(vframe);
+        let (theta_r, r) = hue_center.value();
+        trace!(target: "transform", "Avg theta-r: {:.2}, avg r: {:.2}", theta_r, r);
         (theta_r, r)
     }
 
@@ -464,14 +458,12 @@ impl FrameTransformImpl {
             let theta = diff / r;
             // println!("Theta: {:.2}, Pretheta: {:.2}, Theta_r: {:.2}, r: {:.2}", theta, pretheta, theta_r, r);
             // println!("Translated theta: {:.2}", theta);
-            // PARAM: color spread
-            let color_spread = 0.15 + 0.04 * abs_vol;
+            let color_spread = self.params.color_spread + self.params.color_spread_volume_gain * abs_vol;
             let theta_1 = Self::sigmoid(theta);
             let theta_sig = 2f64 * color_spread * (theta_1 - 0.5f64);
 
-            // PARAM: color spread
             // TODO: create crate for polar coordinates, or use an open source crate
-            let theta_premap = self.angle + theta_sig + 0.05f64 * disturbance;
+            let theta_premap = self.angle + theta_sig + self.params.disturbance_hue_shift * disturbance;
             // let theta_premap = disturbance;
 
             // oh my god the horror
@@ -492,8 +484,7 @@ impl FrameTransformImpl {
             while theta_premap < 0.0 {
                 theta_premap += 2.0 * ::std::f64::consts::PI;
             }
-            // PARAM: gray basecolor
-            let gray_offset = 1.0;
+            let gray_offset = self.params.gray_offset;
             let mut theta_premap = theta_premap + (1.0 - gray) * gray_offset * ::std::f64::consts::PI;
             while theta_premap >= 2.0 * ::std::f64::consts::PI {
                 theta_premap -= 2.0 * ::std::f64::consts::PI;
@@ -519,9 +510,12 @@ impl FrameTransformImpl {
             // let fft_val = ceil_amount * fft_ceil + (1f64 - ceil_amount) * fft_floor;
             // println!("Disturbance: {:.2}", disturbance);
             let gray_val = self.premap.grayscale[e];
-            let base_saturation = 64.0 * (1.0 + abs_vol * 0.3);
-            let gray_saturation = 90.0 * (1.0 + abs_vol * 0.3);
-            gray_val * gray_saturation + (1.0-gray_val) * (base_saturation + 2f64 * self.premap.magnitude[e] + 16f64 * disturbance)
+            let params = &self.params;
+            let base_saturation = params.base_saturation * (1.0 + abs_vol * params.saturation_volume_gain);
+            let gray_saturation = params.gray_saturation * (1.0 + abs_vol * params.saturation_volume_gain);
+            gray_val * gray_saturation + (1.0-gray_val) * (base_saturation
+                + params.saturation_magnitude_gain * self.premap.magnitude[e]
+                + params.saturation_disturbance_gain * disturbance)
         }).collect()
     }
 
@@ -615,7 +609,7 @@ impl FrameTransform for FrameTransformImpl {
         // } else {
         //     self.frame_counter = 0;
         // }
-        if aframe.sum() == 0f64 {
+        if aframe.is_silent() {
             return;
         }
 
@@ -636,14 +630,27 @@ impl FrameTransform for FrameTransformImpl {
         // self.fft.as_mut().unwrap().update(aframe);
     }
 
+    fn process_audio_block(&mut self, block: &mut AudioBlock) {
+        if self.audio_edge.is_none() {
+            self.audio_edge = Some(NormalizedAudioEdgeMeasure::new(&block.format));
+        }
+
+        if self.audio_volume.is_none() {
+            self.audio_volume = Some(NormalizedAudioVolumeMeasure::new(&block.format));
+        }
+
+        self.audio_edge.as_mut().unwrap().update_block(block);
+        self.audio_volume.as_mut().unwrap().update_block(block);
+    }
+
     fn process_video_frame(&mut self, vframe: &mut VideoFrame, vtime: f64) {
         self.init(vframe);
         let abs_vol = self.get_abs_vol();
         let disturbance = self.get_disturbance();
-        let raw_rotation = (1f64+3f64*abs_vol) * ROTATION_RATE;
+        let raw_rotation = (1f64 + self.params.rotation_volume_gain * abs_vol) * self.params.rotation_rate;
         self.update_angle(raw_rotation, vframe);
         
-        println!("Raw Rotation: {:.2}, Angle: {:.2}, Time: {:.2}, Abs vol: {:.2}, audio_edge: {:.2}",raw_rotation, self.angle, vtime, abs_vol, disturbance);
+        trace!(target: "transform", "Raw Rotation: {:.2}, Angle: {:.2}, Time: {:.2}, Abs vol: {:.2}, audio_edge: {:.2}",raw_rotation, self.angle, vtime, abs_vol, disturbance);
         
         let (theta_r, r) = self.calculate_theta_r(vframe);
 
@@ -659,26 +666,27 @@ impl FrameTransform for FrameTransformImpl {
         // technically the y/u/v channels don't have any data dependency,
         // and the work done above is on small data sizes (65536 u/v colors)
         // this is the heavy lifting, so let's parallelize it.
+        let params = &self.params;
         rayon::scope(|s| {
             s.spawn(|_| {
                 let mut ys = self.calculate_y_pixelmap(vframe);
-                Self::box_edgefilter(&mut ys, 0.0055, vframe.format.width as usize, 1.6);
+                Self::box_edgefilter(&mut ys, params.luma_edge_radius, vframe.format.width as usize, params.luma_edge_strength);
                 // gamma correction
                 let ys: Vec<f64> = ys.iter().map(|y| {
-                    0.68 * y.powf(1.05)
+                    params.luma_gamma_scale * y.powf(params.luma_gamma)
                 }).collect();
                 y_pixelmap = Some(ys);
             });
 
             s.spawn(|_| {
                 let mut us = self.calculate_u_pixelmap(vframe, &uv_framemap);
-                Self::box_blur(&mut us, 0.01, vframe.format.width as usize);
+                Self::box_blur(&mut us, params.chroma_blur_radius, vframe.format.width as usize);
                 u_pixelmap = Some(us);
             });
 
             s.spawn(|_| {
                 let mut vs = self.calculate_v_pixelmap(vframe, &uv_framemap);
-                Self::box_blur(&mut vs, 0.01, vframe.format.width as usize);
+                Self::box_blur(&mut vs, params.chroma_blur_radius, vframe.format.width as usize);
                 v_pixelmap = Some(vs);
             });
         });
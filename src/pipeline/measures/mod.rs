@@ -1,15 +1,19 @@
-use measures::log_ratio_measure::*;
-use measures::exp_decay_measure::*;
-use measures::mean_window_measure::*;
-use measures::sigmoid_measure::*;
-use measures::mean_measure::*;
+use crate::measures::log_ratio_measure::*;
+use crate::measures::exp_decay_measure::*;
+use crate::measures::mean_window_measure::*;
+use crate::measures::sigmoid_measure::*;
+use crate::measures::mean_measure::*;
 
-use audio::audio_format::*;
-use audio::audio_frame::*;
+use crate::audio::audio_block::*;
+use crate::audio::audio_format::*;
+use crate::audio::audio_frame::*;
 
-use measures::*;
+use crate::video::video_format::*;
+use crate::video::video_frame::*;
 
-use pipeline::queue_buf::*;
+use crate::measures::*;
+
+use crate::pipeline::queue_buf::*;
 
 use rustfft::num_complex::*;
 
@@ -17,6 +21,9 @@ use rustfft::num_traits::Zero;
 use rustfft::FFTplanner;
 use rustfft::FFT;
 use apodize::{hanning_iter};
+use stats::mean;
+
+use log::{trace, debug, log_enabled, Level};
 
 pub struct NormalizedAudioEdgeMeasure {
     buf: QueueBuf<f64>,
@@ -47,9 +54,20 @@ impl NormalizedAudioEdgeMeasure {
     }
 
     pub fn update(&mut self, frame: &AudioFrame) {
-        self.buf.push(frame.abs_sum());
+        self.buf.push(frame.abs_mean());
         self.time = frame.time;
     }
+
+    /// Pushes the loudness of each non-silent frame into the edge window and moves the
+    /// measure's time to the end of the block.
+    pub fn update_block(&mut self, block: &AudioBlock) {
+        for (samples, abs_mean) in block.frame_samples().zip(block.abs_means()) {
+            if !is_silent(samples) {
+                self.buf.push(abs_mean);
+            }
+        }
+        self.time = block.end_time();
+    }
 }
 
 impl Measure<(), f64> for NormalizedAudioEdgeMeasure {
@@ -87,10 +105,21 @@ impl NormalizedAudioVolumeMeasure {
     }
 
     pub fn update(&mut self, frame: &AudioFrame) {
-        let sum = frame.abs_sum();
+        let sum = frame.abs_mean();
         self.buf.push(sum);
         self.avg.update(sum);
     }
+
+    /// Feeds the loudness of each non-silent frame to both the recent window and the
+    /// running average it is compared against.
+    pub fn update_block(&mut self, block: &AudioBlock) {
+        for (samples, sum) in block.frame_samples().zip(block.abs_means()) {
+            if !is_silent(samples) {
+                self.buf.push(sum);
+                self.avg.update(sum);
+            }
+        }
+    }
 }
 
 impl Measure<(), f64> for NormalizedAudioVolumeMeasure {
@@ -114,7 +143,7 @@ pub struct FFTMeasure {
 impl FFTMeasure {
     pub fn new(af: &AudioFormat, buckets: usize) -> FFTMeasure {
         let fft_size = 8192;
-        println!("fft size: {}", fft_size);
+        debug!(target: "measures", "fft size: {}", fft_size);
         FFTMeasure {
             buf: QueueBuf::new(vec!(0f64; fft_size)),
             window: hanning_iter(fft_size).collect(),
@@ -124,11 +153,20 @@ impl FFTMeasure {
             bin_size_hz: (af.rate as f64) / (fft_size as f64 / 2f64)
         }
     }
+
+    /// Appends the mono downmix of each non-silent frame to the FFT input.
+    pub fn update_block(&mut self, block: &AudioBlock) {
+        for (samples, mean) in block.frame_samples().zip(block.means()) {
+            if !is_silent(samples) {
+                self.buf.push(mean);
+            }
+        }
+    }
 }
 
 impl<'a, 'b> StatefulMeasure<&'a AudioFrame<'b>, Vec<f64>> for FFTMeasure {
     fn update(&mut self, frame: &AudioFrame) {
-        self.buf.push(frame.sum());
+        self.buf.push(frame.mean());
     }
 
     fn value(&mut self) -> Vec<f64> {
@@ -193,32 +231,98 @@ impl<'a, 'b> StatefulMeasure<&'a AudioFrame<'b>, Vec<f64>> for FFTMeasure {
         
         // println!("Before remap: {:?}", output);
 
-        println!("{} FFT bins from {:.2}Hz to {:.2}Hz", output.len(), min_freq, max_freq);
-        for v in self.smoothed_result.iter() {
-            let v = *v;
-            if v <= 0.01f64 {
-                print!(" ");
-            } else if v <= 0.2f64 {
-                print!(".")
-            } else if v <= 0.4f64 {
-                print!("o")
-            } else if v <= 0.6f64 {
-                print!("e")
-            } else if v <= 0.8f64 {
-                print!("0")
-            } else {
-                print!("#")
-            }
+        if log_enabled!(target: "measures", Level::Trace) {
+            let spectrum: String = self.smoothed_result.iter().map(|v| {
+                let v = *v;
+                if v <= 0.01f64 {
+                    ' '
+                } else if v <= 0.2f64 {
+                    '.'
+                } else if v <= 0.4f64 {
+                    'o'
+                } else if v <= 0.6f64 {
+                    'e'
+                } else if v <= 0.8f64 {
+                    '0'
+                } else {
+                    '#'
+                }
+            }).collect();
+
+            trace!(target: "measures", "{} FFT bins from {:.2}Hz to {:.2}Hz", output.len(), min_freq, max_freq);
+            trace!(target: "measures", "{}", spectrum);
         }
 
-        println!("");
-
         // todo: return reference so copy is not needed
         self.smoothed_result.clone()
     }
 }
 
 
+/// Tracks the dominant hue of the video (theta_r) and how tightly
+/// the frame's colors cluster around it (r), averaged over the last 10 seconds.
+pub struct HueCenterMeasure {
+    theta: Vec<f64>,
+    theta_r_buf: QueueBuf<f64>,
+    r_buf: QueueBuf<f64>
+}
+
+impl HueCenterMeasure {
+    pub fn new(vf: &VideoFormat) -> HueCenterMeasure {
+        let buf_size = vf.frames_in(10.0);
+        HueCenterMeasure {
+            theta: (0..65536).map(|e| {
+                let u = (e / 256) as f64 - 128f64;
+                let v = (e % 256) as f64 - 128f64;
+
+                (v).atan2(u)
+            }).collect(),
+            theta_r_buf: QueueBuf::new(vec![0f64; buf_size]),
+            r_buf: QueueBuf::new(vec![1f64; buf_size])
+        }
+    }
+}
+
+impl<'a, 'b> StatefulMeasure<&'a VideoFrame<'b>, (f64, f64)> for HueCenterMeasure {
+    fn update(&mut self, vframe: &VideoFrame) {
+        let mut u_sum = 0f64;
+        let mut v_sum = 0f64;
+        let mut n_sum = 0usize;
+        let scan_pixels = 257;
+        for pixel in vframe.data.chunks(4 * scan_pixels) {
+            let u = pixel[2];
+            let v = pixel[3];
+            let uv_idx = u as usize * 256 + v as usize;
+            let theta = self.theta[uv_idx];
+
+            u_sum += theta.cos();
+            v_sum += theta.sin();
+            n_sum += 1;
+        }
+
+        let mut theta_r = v_sum.atan2(u_sum);
+        while theta_r >= 2.0 * ::std::f64::consts::PI {
+            theta_r -= 2.0 * ::std::f64::consts::PI;
+        }
+        while theta_r < 0.0 {
+            theta_r += 2.0 * ::std::f64::consts::PI;
+        }
+        let sin_v = v_sum / n_sum as f64;
+        let cos_u = u_sum / n_sum as f64;
+        // println!("v_sum: {}, u_sum: {}, sin_v: {}, cos_u: {}", v_sum, u_sum, sin_v, cos_u);
+        let r = (-(sin_v*sin_v + cos_u*cos_u).ln()).sqrt();
+        trace!(target: "measures", "Theta_r: {:.2}, r: {:.2}", theta_r, r);
+        self.theta_r_buf.push(theta_r);
+        self.r_buf.push(r);
+    }
+
+    fn value(&mut self) -> (f64, f64) {
+        let theta_r = mean(self.theta_r_buf.extract().iter().map(|e| *e));
+        let r = mean(self.r_buf.extract().iter().map(|e| *e));
+        (theta_r, r)
+    }
+}
+
 
 // pub type NormalizedAudioVolumeMeasure = SigmoidMeasure<MeanWindowMeasure<AudioVolumeMeasure>>;
 // pub fn measure_audio_volume(af: &AudioFormat) -> SigmoidMeasure<MeanWindowMeasure<AudioVolumeMeasure>> {
@@ -1,7 +1,17 @@
+pub mod basic_transforms;
+pub mod cancel;
+pub mod encoder_settings;
 pub mod frame_sink;
 pub mod frame_source;
+pub mod frame_still;
+pub mod frame_trace;
 pub mod frame_transform;
+pub mod transform_chain;
+pub mod transform_params;
+pub mod transform_registry;
 pub mod pipeline_utils;
+pub mod progress;
 pub mod measures;
+pub mod output_format;
 
 pub mod queue_buf;
\ No newline at end of file
@@ -0,0 +1,293 @@
+use std::path::Path;
+
+use anyhow::Error;
+
+#[derive(Debug, thiserror::Error)]
+#[error("Unknown container {}, expected mp4, mkv or webm", .0)]
+pub struct UnknownContainer(pub String);
+
+#[derive(Debug, thiserror::Error)]
+#[error("Unknown video codec {}, expected h264, vp8 or vp9", .0)]
+pub struct UnknownVideoCodec(pub String);
+
+#[derive(Debug, thiserror::Error)]
+#[error("Unknown audio codec {}, expected aac, opus, vorbis or flac", .0)]
+pub struct UnknownAudioCodec(pub String);
+
+#[derive(Debug, thiserror::Error)]
+#[error("Codec {} is not supported in {} files", .0, .1)]
+pub struct UnsupportedCodec(pub &'static str, pub &'static str);
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum Container {
+    Mp4,
+    Mkv,
+    WebM
+}
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum VideoCodec {
+    H264,
+    VP8,
+    VP9
+}
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum AudioCodec {
+    Aac,
+    Opus,
+    Vorbis,
+    Flac
+}
+
+impl Container {
+    pub fn from_name(name: &str) -> Result<Container, Error> {
+        match name {
+            "mp4" => Ok(Container::Mp4),
+            "mkv" => Ok(Container::Mkv),
+            "webm" => Ok(Container::WebM),
+            _ => Err(UnknownContainer(name.to_string()))?
+        }
+    }
+
+    /// Picks the container from the output file extension.
+    /// A missing or unknown extension is an error, rather than a guess at what was meant.
+    pub fn from_path(path: &str) -> Result<Container, Error> {
+        let extension = Path::new(path).extension()
+            .and_then(|e| e.to_str())
+            .map(|e| e.to_lowercase());
+
+        match extension.as_ref().map(|e| e.as_str()) {
+            Some("mp4") => Ok(Container::Mp4),
+            Some("mkv") => Ok(Container::Mkv),
+            Some("webm") => Ok(Container::WebM),
+            _ => Err(UnknownContainer(path.to_string()))?
+        }
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            &Container::Mp4 => "mp4",
+            &Container::Mkv => "mkv",
+            &Container::WebM => "webm"
+        }
+    }
+
+    /// The gstreamer muxer element.
+    pub fn muxer(&self) -> &'static str {
+        match self {
+            &Container::Mp4 => "mp4mux",
+            &Container::Mkv => "matroskamux",
+            &Container::WebM => "webmmux"
+        }
+    }
+
+    /// Codecs used when none are given.  MKV keeps a lossless FLAC audio master.
+    pub fn default_codecs(&self) -> (VideoCodec, AudioCodec) {
+        match self {
+            &Container::Mp4 => (VideoCodec::H264, AudioCodec::Aac),
+            &Container::Mkv => (VideoCodec::H264, AudioCodec::Flac),
+            &Container::WebM => (VideoCodec::VP9, AudioCodec::Opus)
+        }
+    }
+
+    pub fn supports_video(&self, codec: VideoCodec) -> bool {
+        match (self, codec) {
+            (&Container::Mp4, VideoCodec::H264) => true,
+            (&Container::Mp4, _) => false,
+            (&Container::Mkv, _) => true,
+            (&Container::WebM, VideoCodec::H264) => false,
+            (&Container::WebM, _) => true
+        }
+    }
+
+    pub fn supports_audio(&self, codec: AudioCodec) -> bool {
+        match (self, codec) {
+            (&Container::Mp4, AudioCodec::Aac) => true,
+            (&Container::Mp4, _) => false,
+            (&Container::Mkv, _) => true,
+            (&Container::WebM, AudioCodec::Opus) | (&Container::WebM, AudioCodec::Vorbis) => true,
+            (&Container::WebM, _) => false
+        }
+    }
+
+    /// Checks that both codecs can be muxed into this container.
+    pub fn check(&self, video_codec: VideoCodec, audio_codec: AudioCodec) -> Result<(), Error> {
+        if !self.supports_video(video_codec) {
+            Err(UnsupportedCodec(video_codec.name(), self.name()))?;
+        }
+
+        if !self.supports_audio(audio_codec) {
+            Err(UnsupportedCodec(audio_codec.name(), self.name()))?;
+        }
+
+        Ok(())
+    }
+}
+
+impl VideoCodec {
+    pub fn from_name(name: &str) -> Result<VideoCodec, Error> {
+        match name {
+            "h264" => Ok(VideoCodec::H264),
+            "vp8" => Ok(VideoCodec::VP8),
+            "vp9" => Ok(VideoCodec::VP9),
+            _ => Err(UnknownVideoCodec(name.to_string()))?
+        }
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            &VideoCodec::H264 => "h264",
+            &VideoCodec::VP8 => "vp8",
+            &VideoCodec::VP9 => "vp9"
+        }
+    }
+
+    /// The gstreamer encoder element.
+    pub fn encoder(&self) -> &'static str {
+        match self {
+            &VideoCodec::H264 => "x264enc",
+            &VideoCodec::VP8 => "vp8enc",
+            &VideoCodec::VP9 => "vp9enc"
+        }
+    }
+}
+
+impl AudioCodec {
+    pub fn from_name(name: &str) -> Result<AudioCodec, Error> {
+        match name {
+            "aac" => Ok(AudioCodec::Aac),
+            "opus" => Ok(AudioCodec::Opus),
+            "vorbis" => Ok(AudioCodec::Vorbis),
+            "flac" => Ok(AudioCodec::Flac),
+            _ => Err(UnknownAudioCodec(name.to_string()))?
+        }
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            &AudioCodec::Aac => "aac",
+            &AudioCodec::Opus => "opus",
+            &AudioCodec::Vorbis => "vorbis",
+            &AudioCodec::Flac => "flac"
+        }
+    }
+
+    /// The gstreamer encoder element.
+    pub fn encoder(&self) -> &'static str {
+        match self {
+            &AudioCodec::Aac => "faac",
+            &AudioCodec::Opus => "opusenc",
+            &AudioCodec::Vorbis => "vorbisenc",
+            &AudioCodec::Flac => "flacenc"
+        }
+    }
+}
+
+#[derive(Debug, thiserror::Error)]
+#[error("Unknown lossless format {}, expected ffv1, h264 or y4m", .0)]
+pub struct UnknownLosslessFormat(pub String);
+
+/// Lossless outputs, for handing renders on to an editor without another generation of loss.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum LosslessFormat {
+    /// FFV1 video and FLAC audio in MKV
+    Ffv1,
+    /// lossless (quantizer 0) 4:4:4 x264 video and FLAC audio in MKV
+    H264,
+    /// uncompressed Y4M video, with the audio in a WAV file next to it
+    Y4m
+}
+
+impl LosslessFormat {
+    pub fn from_name(name: &str) -> Result<LosslessFormat, Error> {
+        match name {
+            "ffv1" => Ok(LosslessFormat::Ffv1),
+            "h264" => Ok(LosslessFormat::H264),
+            "y4m" => Ok(LosslessFormat::Y4m),
+            _ => Err(UnknownLosslessFormat(name.to_string()))?
+        }
+    }
+
+    /// The muxer element, if video and audio share a file.
+    pub fn muxer(&self) -> Option<&'static str> {
+        match self {
+            &LosslessFormat::Ffv1 | &LosslessFormat::H264 => Some("matroskamux"),
+            &LosslessFormat::Y4m => None
+        }
+    }
+
+    /// The gstreamer video encoder element.
+    pub fn video_encoder(&self) -> &'static str {
+        match self {
+            &LosslessFormat::Ffv1 => "avenc_ffv1",
+            &LosslessFormat::H264 => "x264enc",
+            &LosslessFormat::Y4m => "y4menc"
+        }
+    }
+
+    /// The gstreamer audio encoder element.
+    pub fn audio_encoder(&self) -> &'static str {
+        match self {
+            &LosslessFormat::Ffv1 | &LosslessFormat::H264 => "flacenc",
+            &LosslessFormat::Y4m => "wavenc"
+        }
+    }
+
+    /// The WAV file written next to a Y4M output, e.g. render.wav for render.y4m.
+    pub fn audio_path(uri: &str) -> String {
+        Path::new(uri).with_extension("wav").to_string_lossy().into_owned()
+    }
+}
+
+#[derive(Debug, thiserror::Error)]
+#[error("Unknown image format for {}, expected a .png, .jpg or .jpeg pattern", .0)]
+pub struct UnknownImageFormat(pub String);
+
+/// Still image formats for image sequence output.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum ImageFormat {
+    Png,
+    Jpeg
+}
+
+impl ImageFormat {
+    /// Picks the format from the extension of a file name pattern such as frames/%05d.png.
+    pub fn from_path(path: &str) -> Result<ImageFormat, Error> {
+        let extension = Path::new(path).extension()
+            .and_then(|e| e.to_str())
+            .map(|e| e.to_lowercase());
+
+        match extension.as_ref().map(|e| e.as_str()) {
+            Some("png") => Ok(ImageFormat::Png),
+            Some("jpg") | Some("jpeg") => Ok(ImageFormat::Jpeg),
+            _ => Err(UnknownImageFormat(path.to_string()))?
+        }
+    }
+
+    /// The gstreamer encoder element.
+    pub fn encoder(&self) -> &'static str {
+        match self {
+            &ImageFormat::Png => "pngenc",
+            &ImageFormat::Jpeg => "jpegenc"
+        }
+    }
+
+    /// The WAV file written next to the images.
+    /// The frame number is dropped from the pattern, so frames/shot_%05d.png gets frames/shot.wav.
+    pub fn audio_path(pattern: &str) -> String {
+        let path = Path::new(pattern);
+        let stem = path.file_stem().and_then(|e| e.to_str()).unwrap_or("");
+        let name = match stem.find('%') {
+            Some(index) => {
+                let end = stem[index..].find('d').map(|e| index + e + 1).unwrap_or(stem.len());
+                format!("{}{}", &stem[..index], &stem[end..])
+            },
+            None => stem.to_string()
+        };
+
+        let name = name.trim_matches(|c| c == '_' || c == '-' || c == '.');
+        let name = if name.is_empty() { "audio" } else { name };
+        path.with_file_name(format!("{}.wav", name)).to_string_lossy().into_owned()
+    }
+}
@@ -1,16 +1,31 @@
-extern crate gstreamer as gst;
+use gstreamer as gst;
+use gstreamer::glib;
 use gstreamer::prelude::*;
 
-extern crate glib;
+use crate::pipeline::cancel::*;
 
-extern crate failure;
-use failure::Error;
-use std::error::Error as StdError;
+use log::{debug, warn};
+use anyhow::Error;
+use std::thread::JoinHandle;
+use std::time::{Duration, Instant};
 
-#[derive(Debug, Fail)]
-#[fail(display = "Missing element {}", _0)]
+/// How often a cancellable pipeline checks for cancellation while waiting on its bus.
+const BUS_POLL_MS: u64 = 100;
+/// How long a cancelled source pipeline gets to deliver the EOS sent into it.
+const CANCEL_EOS_TIMEOUT: Duration = Duration::from_secs(2);
+
+#[derive(Debug, thiserror::Error)]
+#[error("Missing element {}", .0)]
 pub struct MissingElement(pub &'static str);
 
+#[derive(Debug, thiserror::Error)]
+#[error("Failed to push {} buffer into the output pipeline: {:?}", .0, .1)]
+pub struct PushFailed(pub &'static str, pub gst::FlowError);
+
+#[derive(Debug, thiserror::Error)]
+#[error("The {} thread panicked", .0)]
+pub struct WorkerPanicked(pub &'static str);
+
 pub struct PipelineUtils {
 
 }
@@ -18,67 +33,70 @@ pub struct PipelineUtils {
 impl PipelineUtils {
     pub fn start<T: IntoPipeline>(into: &T) -> Result<(), Error> {
         let pipeline = into.into_pipeline();
-        pipeline.set_state(gst::State::Playing).into_result()?;
+        pipeline.set_state(gst::State::Playing)?;
         Ok(())
     }
 
     pub fn message<T: IntoPipeline>(into: &T) -> Result<(), Error> {
-        let pipeline = into.into_pipeline();        
+        Self::wait(into.into_pipeline(), false)
+    }
+
+    /// Waits for EOS or an error on the bus.  A cancellable pipeline gets an EOS event once
+    /// cancelled, and is given up on if the EOS doesn't reach the bus in time.
+    fn wait(pipeline: &gst::Pipeline, cancellable: bool) -> Result<(), Error> {
         let bus = pipeline
-            .get_bus()
+            .bus()
             .expect("Pipeline without bus. Shouldn't happen!");
 
+        let timeout = if cancellable { Some(gst::ClockTime::from_mseconds(BUS_POLL_MS)) } else { gst::ClockTime::NONE };
+        let mut eos_sent: Option<Instant> = None;
+        loop {
+            if cancellable && Cancel::is_cancelled() {
+                match eos_sent {
+                    None => {
+                        debug!(target: "pipeline", "Cancelled, sending EOS");
+                        pipeline.send_event(gst::event::Eos::new());
+                        eos_sent = Some(Instant::now());
+                    },
+                    Some(sent) if sent.elapsed() > CANCEL_EOS_TIMEOUT => {
+                        warn!(target: "pipeline", "No EOS {:?} after cancelling, stopping the pipeline", CANCEL_EOS_TIMEOUT);
+                        break;
+                    },
+                    Some(_) => {}
+                }
+            }
 
-        while let Some(msg) = bus.timed_pop(gst::CLOCK_TIME_NONE) {
-            use gstreamer::MessageView;
+            let msg = match bus.timed_pop(timeout) {
+                Some(msg) => msg,
+                None if cancellable => continue,
+                // only a flushing bus returns nothing without a timeout
+                None => break
+            };
+
+            use gst::MessageView;
 
             match msg.view() {
                 MessageView::Eos(_) => break,
                 MessageView::Error(err) => {
-                    pipeline.set_state(gst::State::Null).into_result()?;
-
-                    #[cfg(feature = "v1_10")]
-                    {
-                        match err.get_details() {
-                            Some(details) if details.get_name() == "error-details" => details
-                                .get::<&glib::AnySendValue>("error")
-                                .cloned()
-                                .and_then(|v| {
-                                    v.downcast_ref::<Arc<Mutex<Option<Error>>>>()
-                                        .and_then(|v| v.lock().unwrap().take())
-                                })
-                                .map(Result::Err)
-                                .expect("error-details message without actual error"),
-                            _ => Err(ErrorMessage {
-                                src: msg.get_src()
-                                    .map(|s| s.get_path_string())
-                                    .unwrap_or_else(|| String::from("None")),
-                                error: err.get_error().description().into(),
-                                debug: err.get_debug(),
-                                cause: err.get_error(),
-                            }.into()),
-                        }?;
-                    }
-                    #[cfg(not(feature = "v1_10"))]
-                    {
-                        Err(ErrorMessage {
-                            src: msg.get_src()
-                                .map(|s| s.get_path_string())
-                                .unwrap_or_else(|| String::from("None")),
-                            error: err.get_error().description().into(),
-                            debug: err.get_debug(),
-                            cause: err.get_error(),
-                        })?;
-                    }
-                    break;
+                    pipeline.set_state(gst::State::Null)?;
+
+                    Err(ErrorMessage {
+                        src: msg.src()
+                            .map(|s| s.path_string().to_string())
+                            .unwrap_or_else(|| String::from("None")),
+                        error: err.error().to_string(),
+                        debug: err.debug().map(|d| d.to_string()),
+                        cause: err.error(),
+                    })?;
                 }
                 MessageView::StateChanged(s) => {
-                    println!(
+                    debug!(
+                        target: "pipeline",
                         "State changed from {:?}: {:?} -> {:?} ({:?})",
-                        msg.get_src().map(|s| s.get_path_string()),
-                        s.get_old(),
-                        s.get_current(),
-                        s.get_pending()
+                        msg.src().map(|s| s.path_string()),
+                        s.old(),
+                        s.current(),
+                        s.pending()
                     );
                 }
                 _ => (),
@@ -88,19 +106,34 @@ impl PipelineUtils {
         Ok(())
     }
 
-    pub fn stop <T: IntoPipeline>(into: &T) -> Result<(), Error> {
-        let pipeline = into.into_pipeline();    
-        pipeline.set_state(gst::State::Null).into_result()?;
+    pub fn stop<T: IntoPipeline>(into: &T) -> Result<(), Error> {
+        let pipeline = into.into_pipeline();
+        pipeline.set_state(gst::State::Null)?;
         Ok(())
     }
 
     pub fn run<T: IntoPipeline>(into: &T) -> Result<(), Error> {
-        println!("Running pipeline!");
-        let pipeline = into.into_pipeline();
-        Self::start(pipeline);
-        Self::message(pipeline);
-        Self::stop(pipeline);
-        Ok(())
+        Self::run_until(into.into_pipeline(), false)
+    }
+
+    /// Like run, for a FrameSource pipeline.  Once cancelled, its appsinks refuse buffers by returning EOS
+    /// upstream, which doesn't guarantee an EOS message on the bus, so run alone could wait forever.
+    pub fn run_source<T: IntoPipeline>(into: &T) -> Result<(), Error> {
+        Self::run_until(into.into_pipeline(), true)
+    }
+
+    fn run_until(pipeline: &gst::Pipeline, cancellable: bool) -> Result<(), Error> {
+        debug!(target: "pipeline", "Running pipeline!");
+        Self::start(pipeline)?;
+        // stop even if the pipeline failed, but report the failure rather than the stop
+        let result = Self::wait(pipeline, cancellable);
+        Self::stop(pipeline)?;
+        result
+    }
+
+    /// Joins a worker thread, turning a panic into an error.
+    pub fn join<T>(handle: JoinHandle<T>, name: &'static str) -> Result<T, Error> {
+        handle.join().map_err(|_| Error::from(WorkerPanicked(name)))
     }
 }
 
@@ -120,11 +153,11 @@ impl<'a> IntoPipeline for &'a gst::Pipeline {
     }
 }
 
-#[derive(Debug, Fail)]
-#[fail(display = "Received error from {}: {} (debug: {:?})", src, error, debug)]
+#[derive(Debug, thiserror::Error)]
+#[error("Received error from {}: {} (debug: {:?})", .src, .error, .debug)]
 struct ErrorMessage {
     pub src: String,
     pub error: String,
     pub debug: Option<String>,
-    #[cause] pub cause: glib::Error,
+    #[source] pub cause: glib::Error,
 }
\ No newline at end of file
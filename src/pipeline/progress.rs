@@ -0,0 +1,179 @@
+use std::io::Write;
+use std::io;
+use std::sync::Arc;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+
+use log::error;
+use serde::Serialize;
+use anyhow::Error;
+
+#[derive(Debug, thiserror::Error)]
+#[error("Unknown progress mode {}, expected human, json or quiet", .0)]
+pub struct UnknownProgressMode(pub String);
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum ProgressMode {
+    /// a status line, redrawn in place
+    Human,
+    /// one JSON object per line
+    Json,
+    Quiet
+}
+
+impl ProgressMode {
+    pub fn from_name(name: &str) -> Result<ProgressMode, Error> {
+        match name {
+            "human" => Ok(ProgressMode::Human),
+            "json" => Ok(ProgressMode::Json),
+            "quiet" => Ok(ProgressMode::Quiet),
+            _ => Err(UnknownProgressMode(name.to_string()))?
+        }
+    }
+
+    fn interval(&self) -> Duration {
+        match self {
+            &ProgressMode::Human => Duration::from_millis(500),
+            _ => Duration::from_secs(1)
+        }
+    }
+}
+
+#[derive(Serialize)]
+struct ProgressRecord {
+    time: f64,
+    duration: Option<f64>,
+    percent: Option<f64>,
+    frames: u64,
+    fps: f64,
+    speed: f64,
+    eta: Option<f64>,
+    done: bool
+}
+
+/// Shared handle to the input duration, which is only known once the source has prerolled.
+pub type SharedDuration = Arc<Mutex<Option<f64>>>;
+
+/// Reports how far a render is, on stderr so it stays apart from the pipeline output.
+/// Times are input times.  The range start and end, if any, bound the work to do.
+pub struct Progress {
+    mode: ProgressMode,
+    duration: SharedDuration,
+    start: f64,
+    end: Option<f64>,
+    frames: u64,
+    time: f64,
+    started: Instant,
+    last_report: Option<Instant>
+}
+
+impl Progress {
+    pub fn new(mode: ProgressMode, start: f64, end: Option<f64>) -> Progress {
+        Progress {
+            mode: mode,
+            duration: Arc::new(Mutex::new(None)),
+            start: start,
+            end: end,
+            frames: 0,
+            time: start,
+            started: Instant::now(),
+            last_report: None
+        }
+    }
+
+    pub fn quiet() -> Progress {
+        Self::new(ProgressMode::Quiet, 0f64, None)
+    }
+
+    /// A handle for setting the input duration once it's known.
+    pub fn duration(&self) -> SharedDuration {
+        self.duration.clone()
+    }
+
+    pub fn video_frame(&mut self, time: f64) {
+        self.frames += 1;
+        self.update(time);
+    }
+
+    pub fn audio_block(&mut self, time: f64) {
+        self.update(time);
+    }
+
+    fn update(&mut self, time: f64) {
+        if time > self.time {
+            self.time = time;
+        }
+
+        let due = match self.last_report {
+            Some(last) => last.elapsed() >= self.mode.interval(),
+            None => true
+        };
+
+        if due {
+            self.report(false);
+        }
+    }
+
+    pub fn finish(&mut self) {
+        self.report(true);
+        if self.mode == ProgressMode::Human {
+            eprintln!("");
+        }
+    }
+
+    fn record(&self, done: bool) -> ProgressRecord {
+        let elapsed = self.started.elapsed();
+        let elapsed = elapsed.as_secs() as f64 + elapsed.subsec_nanos() as f64 / 1_000_000_000f64;
+        let processed = (self.time - self.start).max(0f64);
+
+        let end = self.end.or(*self.duration.lock().unwrap());
+        let total = end.map(|e| (e - self.start).max(0f64));
+
+        let speed = if elapsed > 0f64 { processed / elapsed } else { 0f64 };
+        let percent = total.map(|e| if done || e == 0f64 { 100f64 } else { (100f64 * processed / e).min(100f64) });
+        let eta = total.and_then(|e| if speed > 0f64 { Some((e - processed).max(0f64) / speed) } else { None });
+
+        ProgressRecord {
+            time: processed,
+            duration: total,
+            percent: percent,
+            frames: self.frames,
+            fps: if elapsed > 0f64 { self.frames as f64 / elapsed } else { 0f64 },
+            speed: speed,
+            eta: if done { Some(0f64) } else { eta },
+            done: done
+        }
+    }
+
+    fn report(&mut self, done: bool) {
+        self.last_report = Some(Instant::now());
+        let record = self.record(done);
+
+        match self.mode {
+            ProgressMode::Human => {
+                let percent = record.percent.map(|e| format!("{:5.1}%", e)).unwrap_or("    ?%".to_string());
+                let eta = record.eta.map(|e| format_seconds(e)).unwrap_or("--:--".to_string());
+                eprint!("\r{}  {}  frame {}  {:.1} fps  {:.2}x realtime  ETA {}   ",
+                    percent, format_seconds(record.time), record.frames, record.fps, record.speed, eta);
+                io::stderr().flush().ok();
+            },
+            ProgressMode::Json => {
+                match serde_json::to_string(&record) {
+                    Ok(line) => eprintln!("{}", line),
+                    Err(e) => error!(target: "sink", "Error writing progress: {}", e)
+                }
+            },
+            ProgressMode::Quiet => {}
+        }
+    }
+}
+
+fn format_seconds(seconds: f64) -> String {
+    let seconds = seconds.round() as u64;
+    if seconds >= 3600 {
+        format!("{}:{:02}:{:02}", seconds / 3600, (seconds / 60) % 60, seconds % 60)
+    } else {
+        format!("{:02}:{:02}", seconds / 60, seconds % 60)
+    }
+}
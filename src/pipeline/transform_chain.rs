@@ -0,0 +1,203 @@
+use crate::audio::audio_block::*;
+use crate::audio::audio_frame::*;
+use crate::video::video_frame::*;
+
+use crate::measures::*;
+
+use crate::pipeline::frame_transform::*;
+use crate::pipeline::measures::*;
+
+use std::sync::Arc;
+use std::sync::Mutex;
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::audio::audio_format::*;
+
+    /// Records its name and applies its op to every sample, so both the order of calls
+    /// and the frame each stage was given can be checked.
+    struct RecordingStage {
+        name: &'static str,
+        op: fn(f32) -> f32,
+        calls: Arc<Mutex<Vec<&'static str>>>
+    }
+
+    impl FrameTransform for RecordingStage {
+        fn process_audio_frame(&mut self, aframe: &mut AudioFrame, _atime: f64) {
+            self.calls.lock().unwrap().push(self.name);
+            for sample in aframe.data.iter_mut() {
+                *sample = (self.op)(*sample);
+            }
+        }
+
+        fn process_video_frame(&mut self, _vframe: &mut VideoFrame, _vtime: f64) {}
+    }
+
+    #[test]
+    pub fn test_stages_run_in_order() {
+        let calls = Arc::new(Mutex::new(Vec::new()));
+        let mut chain = TransformChain::new()
+            .then(RecordingStage {name: "add", op: |e| e + 1f32, calls: calls.clone()})
+            .then(RecordingStage {name: "double", op: |e| e * 2f32, calls: calls.clone()});
+        assert_eq!(2, chain.len());
+
+        let format = AudioFormat::new(4, 2);
+        let mut data = vec![1f32, 2f32];
+        chain.process_audio_frame(&mut AudioFrame::new(&mut data, &format, 0f64), 0f64);
+
+        assert_eq!(vec!["add", "double"], *calls.lock().unwrap());
+        // (x + 1) * 2, not x * 2 + 1
+        assert_eq!(vec![4f32, 6f32], data);
+    }
+
+    #[test]
+    pub fn test_empty_chain() {
+        let chain = TransformChain::default();
+        assert!(chain.is_empty());
+        assert!(!chain.then(TransformChain::new()).is_empty());
+    }
+}
+
+/// Runs an ordered list of transforms on every audio and video frame.
+/// Each stage sees the frame after all of the previous stages have processed it.
+pub struct TransformChain {
+    stages: Vec<Box<dyn FrameTransform + Send>>
+}
+
+impl TransformChain {
+    pub fn new() -> TransformChain {
+        TransformChain {
+            stages: Vec::new()
+        }
+    }
+
+    pub fn then<T: FrameTransform + Send + 'static>(mut self, stage: T) -> TransformChain {
+        self.stages.push(Box::new(stage));
+        self
+    }
+
+    pub fn push(&mut self, stage: Box<dyn FrameTransform + Send>) {
+        self.stages.push(stage);
+    }
+
+    pub fn len(&self) -> usize {
+        self.stages.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.stages.is_empty()
+    }
+}
+
+impl Default for TransformChain {
+    fn default() -> TransformChain {
+        TransformChain::new()
+    }
+}
+
+impl FrameTransform for TransformChain {
+    fn process_audio_frame(&mut self, aframe: &mut AudioFrame, atime: f64) {
+        for stage in self.stages.iter_mut() {
+            stage.process_audio_frame(aframe, atime);
+        }
+    }
+
+    fn process_audio_block(&mut self, block: &mut AudioBlock) {
+        for stage in self.stages.iter_mut() {
+            stage.process_audio_block(block);
+        }
+    }
+
+    fn process_video_frame(&mut self, vframe: &mut VideoFrame, vtime: f64) {
+        for stage in self.stages.iter_mut() {
+            stage.process_video_frame(vframe, vtime);
+        }
+    }
+}
+
+/// Audio levels published by an AudioLevelsStage, for later stages in the chain to read.
+/// Values are refreshed once per video frame, before later stages process that frame.
+#[derive(Copy, Clone, Debug)]
+pub struct AudioLevels {
+    pub volume: f64,
+    pub edge: f64,
+    pub time: f64
+}
+
+impl AudioLevels {
+    pub fn new() -> AudioLevels {
+        AudioLevels {
+            volume: 0f64,
+            edge: 0f64,
+            time: 0f64
+        }
+    }
+}
+
+pub type SharedAudioLevels = Arc<Mutex<AudioLevels>>;
+
+/// An analysis-only stage. It feeds the audio measures and publishes their values,
+/// leaving the frames untouched.
+pub struct AudioLevelsStage {
+    audio_edge: Option<NormalizedAudioEdgeMeasure>,
+    audio_volume: Option<NormalizedAudioVolumeMeasure>,
+    levels: SharedAudioLevels
+}
+
+impl AudioLevelsStage {
+    pub fn new() -> AudioLevelsStage {
+        AudioLevelsStage {
+            audio_edge: None,
+            audio_volume: None,
+            levels: Arc::new(Mutex::new(AudioLevels::new()))
+        }
+    }
+
+    /// A handle to the published levels, to be given to later stages.
+    pub fn levels(&self) -> SharedAudioLevels {
+        self.levels.clone()
+    }
+}
+
+impl FrameTransform for AudioLevelsStage {
+    fn process_audio_frame(&mut self, aframe: &mut AudioFrame, atime: f64) {
+        if aframe.is_silent() {
+            return;
+        }
+
+        if self.audio_edge.is_none() {
+            self.audio_edge = Some(NormalizedAudioEdgeMeasure::new(&aframe.format));
+        }
+
+        if self.audio_volume.is_none() {
+            self.audio_volume = Some(NormalizedAudioVolumeMeasure::new(&aframe.format));
+        }
+
+        self.audio_edge.as_mut().unwrap().update(aframe);
+        self.audio_volume.as_mut().unwrap().update(aframe);
+    }
+
+    fn process_audio_block(&mut self, block: &mut AudioBlock) {
+        if self.audio_edge.is_none() {
+            self.audio_edge = Some(NormalizedAudioEdgeMeasure::new(&block.format));
+        }
+
+        if self.audio_volume.is_none() {
+            self.audio_volume = Some(NormalizedAudioVolumeMeasure::new(&block.format));
+        }
+
+        self.audio_edge.as_mut().unwrap().update_block(block);
+        self.audio_volume.as_mut().unwrap().update_block(block);
+    }
+
+    fn process_video_frame(&mut self, vframe: &mut VideoFrame, vtime: f64) {
+        let volume = self.audio_volume.as_mut().map(|e| e.value(())).unwrap_or(0f64);
+        let edge = self.audio_edge.as_mut().map(|e| e.value(())).unwrap_or(0f64);
+
+        let mut levels = self.levels.lock().unwrap();
+        levels.volume = if volume.is_nan() { 0f64 } else { volume };
+        levels.edge = if edge.is_nan() { 0f64 } else { edge };
+        levels.time = vframe.time;
+    }
+}
@@ -0,0 +1,167 @@
+use std::fs::File;
+use std::io::Read;
+use std::path::Path;
+
+use serde::{Serialize, Deserialize};
+use anyhow::Error;
+
+/// Every look parameter of FrameTransformImpl.
+/// Loaded from a TOML or JSON file with `--config`.
+/// Keys missing from the file keep their default value, so a config only needs the knobs it changes.
+/// Unknown keys are an error, so a misspelt knob doesn't silently keep its default.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct TransformParams {
+    /// hue rotations per second at zero volume
+    pub rotation_rate: f64,
+    /// how much the normalized volume speeds up the rotation
+    pub rotation_volume_gain: f64,
+
+    /// hue spread around the frame's average hue, in rotations
+    pub color_spread: f64,
+    /// how much the normalized volume widens the hue spread
+    pub color_spread_volume_gain: f64,
+    /// hue shift caused by audio edges, in rotations
+    pub disturbance_hue_shift: f64,
+
+    /// chroma magnitude below which a color is treated as gray
+    pub gray_zero_point: f64,
+    /// falloff of the gray mask
+    pub gray_exponent: f64,
+    /// hue offset applied to grays, in half rotations
+    pub gray_offset: f64,
+
+    /// divisor of the sigmoid luma curve. lower values brighten the image
+    pub luma_sigmoid_div: f64,
+    /// luma edge filter radius, as a fraction of the frame width
+    pub luma_edge_radius: f64,
+    /// luma edge filter strength
+    pub luma_edge_strength: f64,
+    /// luma gain applied after the edge filter
+    pub luma_gamma_scale: f64,
+    /// luma exponent applied after the edge filter
+    pub luma_gamma: f64,
+
+    /// chroma blur radius, as a fraction of the frame width
+    pub chroma_blur_radius: f64,
+
+    /// saturation of colored pixels
+    pub base_saturation: f64,
+    /// saturation of gray pixels
+    pub gray_saturation: f64,
+    /// how much the normalized volume boosts saturation
+    pub saturation_volume_gain: f64,
+    /// how much the source chroma magnitude boosts saturation
+    pub saturation_magnitude_gain: f64,
+    /// how much audio edges boost saturation
+    pub saturation_disturbance_gain: f64
+}
+
+impl Default for TransformParams {
+    fn default() -> TransformParams {
+        TransformParams {
+            rotation_rate: 1f64 / 10f64,
+            rotation_volume_gain: 3.0,
+
+            color_spread: 0.15,
+            color_spread_volume_gain: 0.04,
+            disturbance_hue_shift: 0.05,
+
+            gray_zero_point: 100.0,
+            gray_exponent: 0.35,
+            gray_offset: 1.0,
+
+            luma_sigmoid_div: 72.0,
+            luma_edge_radius: 0.0055,
+            luma_edge_strength: 1.6,
+            luma_gamma_scale: 0.68,
+            luma_gamma: 1.05,
+
+            chroma_blur_radius: 0.01,
+
+            base_saturation: 64.0,
+            gray_saturation: 90.0,
+            saturation_volume_gain: 0.3,
+            saturation_magnitude_gain: 2.0,
+            saturation_disturbance_gain: 16.0
+        }
+    }
+}
+
+/// The config file table holding the EncoderSettings.  Every other top level key is a look parameter.
+pub const ENCODER_TABLE: &'static str = "encoder";
+
+impl TransformParams {
+    /// Loads params from the top level of a config file, skipping the encoder table.
+    pub fn load(path: &str) -> Result<TransformParams, Error> {
+        let mut config = load_config(path)?;
+        if let Some(table) = config.as_object_mut() {
+            table.remove(ENCODER_TABLE);
+        }
+        Ok(serde_json::from_value(config)?)
+    }
+}
+
+/// Parses a config file into a value, which each table is deserialized from on its own.
+/// `.json` files are parsed as JSON, anything else as TOML.
+pub fn load_config(path: &str) -> Result<serde_json::Value, Error> {
+    let mut contents = String::new();
+    File::open(path)?.read_to_string(&mut contents)?;
+
+    let is_json = Path::new(path).extension().map(|e| e == "json").unwrap_or(false);
+    if is_json {
+        Ok(serde_json::from_str(&contents)?)
+    } else {
+        Ok(toml::from_str(&contents)?)
+    }
+}
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::env;
+    use std::fs;
+
+    fn write_config(name: &str, contents: &str) -> String {
+        let path = env::temp_dir().join(format!("recode-{}-{}", ::std::process::id(), name));
+        fs::write(&path, contents).unwrap();
+        path.to_str().unwrap().to_string()
+    }
+
+    #[test]
+    pub fn test_load_keeps_defaults() {
+        let path = write_config("params.toml", "rotation_rate = 0.2\n");
+        let params = TransformParams::load(&path).unwrap();
+        fs::remove_file(&path).ok();
+
+        assert_eq!(0.2, params.rotation_rate);
+        assert_eq!(TransformParams::default().gray_exponent, params.gray_exponent);
+    }
+
+    #[test]
+    pub fn test_load_rejects_unknown_keys() {
+        let path = write_config("misspelt.toml", "rotaton_rate = 0.2\n");
+        let result = TransformParams::load(&path);
+        fs::remove_file(&path).ok();
+
+        assert!(result.is_err());
+    }
+
+    #[test]
+    pub fn test_load_encoder_table() {
+        use crate::pipeline::encoder_settings::*;
+
+        let path = write_config("encoder.toml", "rotation_rate = 0.2\n\n[encoder]\nbitrate = 8000\n");
+        let params = TransformParams::load(&path);
+        let settings = EncoderSettings::load(&path);
+        fs::remove_file(&path).ok();
+
+        assert_eq!(0.2, params.unwrap().rotation_rate);
+        assert_eq!(Some(8000), settings.unwrap().bitrate);
+
+        let path = write_config("encoder-misspelt.toml", "[encoder]\nbitrat = 8000\n");
+        let settings = EncoderSettings::load(&path);
+        fs::remove_file(&path).ok();
+
+        assert!(settings.is_err());
+    }
+}
@@ -0,0 +1,79 @@
+use crate::pipeline::basic_transforms::*;
+use crate::pipeline::frame_transform::*;
+use crate::pipeline::transform_chain::*;
+use crate::pipeline::transform_params::*;
+
+use anyhow::Error;
+
+#[derive(Debug, thiserror::Error)]
+#[error("Unknown transform {}, see --list-transforms", .0)]
+pub struct UnknownTransform(pub String);
+
+pub type TransformConstructor = fn(&TransformParams) -> Box<dyn FrameTransform + Send>;
+
+pub struct TransformEntry {
+    pub name: &'static str,
+    pub description: &'static str,
+    constructor: TransformConstructor
+}
+
+/// Maps transform names to constructors, so the look can be picked per render.
+pub struct TransformRegistry {
+    entries: Vec<TransformEntry>
+}
+
+impl TransformRegistry {
+    pub fn new() -> TransformRegistry {
+        TransformRegistry {
+            entries: Vec::new()
+        }
+    }
+
+    /// The looks that ship with recode.
+    pub fn builtin() -> TransformRegistry {
+        let mut registry = TransformRegistry::new();
+        registry.register("hue", "Audio reactive hue rotation (the default look)",
+            |params| Box::new(FrameTransformImpl::new(params.clone())));
+        registry.register("identity", "Passes audio and video through untouched",
+            |_| Box::new(IdentityTransform::new()));
+        registry.register("debug", "Overlays the audio volume and edge measures",
+            |_| Box::new(LevelsOverlay::with_analysis()));
+        registry
+    }
+
+    /// Registers a constructor.  A later registration with the same name replaces the earlier one.
+    pub fn register(&mut self, name: &'static str, description: &'static str, constructor: TransformConstructor) {
+        self.entries.retain(|e| e.name != name);
+        self.entries.push(TransformEntry {
+            name: name,
+            description: description,
+            constructor: constructor
+        });
+    }
+
+    pub fn entries(&self) -> &[TransformEntry] {
+        self.entries.as_slice()
+    }
+
+    /// Creates the named transform.
+    /// Several names separated by `+` (e.g. `hue+debug`) are run in order as a TransformChain.
+    pub fn create(&self, name: &str, params: &TransformParams) -> Result<Box<dyn FrameTransform + Send>, Error> {
+        let names: Vec<&str> = name.split('+').map(|e| e.trim()).collect();
+        if names.len() == 1 {
+            return self.create_single(names[0], params);
+        }
+
+        let mut chain = TransformChain::new();
+        for name in names {
+            chain.push(self.create_single(name, params)?);
+        }
+        Ok(Box::new(chain))
+    }
+
+    fn create_single(&self, name: &str, params: &TransformParams) -> Result<Box<dyn FrameTransform + Send>, Error> {
+        match self.entries.iter().find(|e| e.name == name) {
+            Some(entry) => Ok((entry.constructor)(params)),
+            None => Err(UnknownTransform(name.to_string()))?
+        }
+    }
+}
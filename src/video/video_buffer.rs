@@ -1,8 +1,68 @@
-extern crate gstreamer as gst;
-extern crate gstreamer_app as gst_app;
+use gstreamer as gst;
+use gstreamer_app as gst_app;
 
-use video::video_format::*;
-use video::video_frame::*;
+use crate::video::video_format::*;
+use crate::video::video_frame::*;
+use crate::pipeline::pipeline_utils::PushFailed;
+
+use anyhow::Error;
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn new_format() -> VideoFormat {
+        VideoFormat::new(gst::Fraction::new(4, 1), 2, 1)
+    }
+
+    fn new_buffer(frames: usize, time: f64) -> VideoBuffer {
+        let format = new_format();
+        let buffer: Vec<u8> = (0..frames * format.frame_size).map(|e| e as u8).collect();
+        VideoBuffer {
+            buffer: buffer,
+            format: format,
+            time: time,
+            clock_time: gst::ClockTime::from_nseconds(0),
+            duration: gst::ClockTime::from_nseconds(0)
+        }
+    }
+
+    #[test]
+    pub fn test_frames() {
+        let buffer = new_buffer(2, 0f64);
+        assert_eq!(2, buffer.num_frames());
+
+        let mut iter = buffer.into_iter();
+        assert_eq!((0u8..8).collect::<Vec<u8>>(), iter.next().unwrap().data.to_vec());
+        assert_eq!((8u8..16).collect::<Vec<u8>>(), iter.next().unwrap().data.to_vec());
+        assert!(!iter.has_next());
+        assert!(iter.next().is_none());
+    }
+
+    #[test]
+    pub fn test_frame_time() {
+        let buffer = new_buffer(3, 2f64);
+        let mut iter = buffer.into_iter();
+
+        let mut times = Vec::new();
+        while let Some(frame) = iter.next() {
+            times.push(frame.time);
+        }
+        assert_eq!(vec![2f64, 2.25f64, 2.5f64], times);
+    }
+
+    #[test]
+    pub fn test_writes_into_buffer() {
+        let buffer = new_buffer(2, 0f64);
+        let mut iter = buffer.into_iter();
+        iter.next();
+        iter.next().unwrap().data[0] = 255u8;
+
+        let buffer = iter.into_buffer();
+        assert_eq!(0u8, buffer.buffer[0]);
+        assert_eq!(255u8, buffer.buffer[8]);
+    }
+}
 
 // TODO: reduce pub usages
 pub struct VideoBuffer {
@@ -14,6 +74,28 @@ pub struct VideoBuffer {
 }
 
 impl VideoBuffer {
+    /// A single black frame, timed as frame `index` of a stream in the given format.
+    pub fn canvas(format: VideoFormat, index: u64) -> VideoBuffer {
+        let mut buffer = Vec::with_capacity(format.frame_size);
+        for _ in 0..format.pixel_count {
+            // AYUV black
+            buffer.extend_from_slice(&[255u8, 16u8, 128u8, 128u8]);
+        }
+
+        let num = format.frame_rate_gst_fraction.numer() as u64;
+        let den = format.frame_rate_gst_fraction.denom() as u64;
+        let duration = gst::ClockTime::SECOND.nseconds() * den / num;
+        let pts = gst::ClockTime::SECOND.nseconds() * index * den / num;
+
+        VideoBuffer {
+            buffer: buffer,
+            format: format,
+            time: index as f64 * format.frame_duration,
+            clock_time: gst::ClockTime::from_nseconds(pts),
+            duration: gst::ClockTime::from_nseconds(duration)
+        }
+    }
+
     pub fn get_frame(&self, index: usize, format: &VideoFormat) -> &[u8] {
         let len = format.frame_size;
         let start = len*index;
@@ -25,11 +107,17 @@ impl VideoBuffer {
         self.buffer.len() / (self.format.frame_size as usize)
     }
 
+    /// Shifts the timestamps so `start` becomes zero.
+    pub fn rebase(&mut self, start: f64) {
+        self.time -= start;
+        self.clock_time = gst::ClockTime::from_nseconds((self.time.max(0f64) * 1_000_000_000f64) as u64);
+    }
+
     pub fn into_iter<'a>(self) -> VideoBufferIter {
         VideoBufferIter::new(self)
     }
 
-    pub fn into_appsrc<'a>(self, appsrc: &'a mut gst_app::AppSrc) {
+    pub fn into_appsrc<'a>(self, appsrc: &'a mut gst_app::AppSrc) -> Result<(), Error> {
 
         // println!("Writing video buffer with time {:?} / duration {:?}", self.clock_time, self.duration);
         let mut buffer = gst::Buffer::with_size(self.buffer.len()).unwrap();
@@ -42,10 +130,8 @@ impl VideoBuffer {
             slice.copy_from_slice(self.buffer.as_slice());
         }
 
-        let res = appsrc.push_buffer(buffer);
-        if res != gst::FlowReturn::Ok {
-            println!("Error writing Video Buffer: {:?}", res);
-        }
+        appsrc.push_buffer(buffer).map_err(|e| PushFailed("video", e))?;
+        Ok(())
     }
 }
 
@@ -74,12 +160,13 @@ impl VideoBufferIter {
     }
 
     pub fn has_next(&self) -> bool {
-        self.pos + self.window <= self.buffer.buffer.len()
+        self.window > 0 && self.pos + self.window <= self.buffer.buffer.len()
     }
 
     pub fn next<'b>(&'b mut self) -> Option<VideoFrame<'b>> {
-        if self.pos + self.window <= self.buffer.buffer.len() {
-            let time = self.buffer.time + self.pos as f64 * self.buffer.format.frame_duration;
+        if self.has_next() {
+            let index = self.pos / self.window;
+            let time = self.buffer.time + index as f64 * self.buffer.format.frame_duration;
 
             let start = self.pos;
             let end = self.pos+self.window;
@@ -87,7 +174,7 @@ impl VideoBufferIter {
             let slice = &mut self.buffer.buffer.as_mut_slice()[start..end];
             let frame = VideoFrame::new(slice, &self.buffer.format, time);
 
-            self.pos += 1;
+            self.pos += self.window;
             
             Some(frame)
         } else { None }
@@ -1,6 +1,6 @@
-extern crate gstreamer as gst;
+use gstreamer as gst;
 
-#[derive(Copy, Clone, Debug)]
+#[derive(Copy, Clone, Debug, PartialEq)]
 pub struct VideoFormat {
     pub frame_rate_gst_fraction: gst::Fraction,
     pub frame_rate: f64,
@@ -27,8 +27,7 @@ impl VideoFormat {
     }
 
     pub fn new(framerate: gst::Fraction, width: i32, height: i32) -> VideoFormat {
-        let (a, b): (i32, i32) = framerate.into();
-        let rate = a as f64 / b as f64;
+        let rate = framerate.numer() as f64 / framerate.denom() as f64;
         VideoFormat {
             frame_rate_gst_fraction: framerate,
             frame_rate: rate,
@@ -41,6 +40,31 @@ impl VideoFormat {
         }
     }
 
+    /// Reads the frame rate and size from negotiated raw video caps.
+    pub fn from_caps(caps: &gst::CapsRef) -> Option<VideoFormat> {
+        let structure = caps.structure(0)?;
+        let framerate = structure.get::<gst::Fraction>("framerate").ok()?;
+        let width = structure.get::<i32>("width").ok()?;
+        let height = structure.get::<i32>("height").ok()?;
+        Some(VideoFormat::new(framerate, width, height))
+    }
+
+    /// Parses a canvas size and rate such as `1280x720@30`.
+    pub fn from_canvas_spec(spec: &str) -> Option<VideoFormat> {
+        let mut size_rate = spec.splitn(2, '@');
+        let size = size_rate.next()?;
+        let rate = size_rate.next().unwrap_or("30").parse::<i32>().ok()?;
+
+        let mut width_height = size.splitn(2, 'x');
+        let width = width_height.next()?.parse::<i32>().ok()?;
+        let height = width_height.next()?.parse::<i32>().ok()?;
+        if width <= 0 || height <= 0 || rate <= 0 {
+            return None;
+        }
+
+        Some(VideoFormat::new(gst::Fraction::new(rate, 1), width, height))
+    }
+
     pub fn frames_in(&self, time: f64) -> usize {
         (time * (self.frame_rate as f64)).ceil() as usize
     }
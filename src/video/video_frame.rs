@@ -1,4 +1,4 @@
-use video::video_format::*;
+use crate::video::video_format::*;
 
 pub struct VideoFrame<'a> {
     pub data: &'a mut [u8],
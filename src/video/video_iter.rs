@@ -3,12 +3,12 @@ use std::sync::Mutex;
 use std::sync::Arc;
 use std::sync::mpsc::Receiver;
 
-use video::video_buffer::*;
-use video::video_format::*;
-use video::video_frame::*;
+use crate::video::video_buffer::*;
+use crate::video::video_format::*;
+use crate::video::video_frame::*;
 
 pub struct VideoIter {
-    video_channel: Box<Iterator<Item=VideoBuffer>>,
+    video_channel: Box<dyn Iterator<Item=VideoBuffer>>,
     video_frame_iterator: Option<VideoBufferIter>,
     finished_buffers: LinkedList<VideoBuffer>
 }
@@ -16,15 +16,22 @@ pub struct VideoIter {
 impl<'i> VideoIter {
     pub fn new(video_channel: Receiver<Arc<Mutex<VideoBuffer>>>) -> VideoIter {
         let video_iter = video_channel.into_iter().map(|e| Mutex::into_inner(Arc::try_unwrap(e).ok().unwrap()).ok().unwrap());
-        let mut processor = VideoIter {
+        Self::from_buffers(Box::new(video_iter))
+    }
+
+    /// Iterates over buffers that don't come from a FrameSource, such as a generated canvas.
+    /// The first buffer is pulled lazily, so an iterator over a stream that never arrives doesn't block.
+    pub fn from_buffers(buffers: Box<dyn Iterator<Item=VideoBuffer>>) -> VideoIter {
+        VideoIter {
             video_frame_iterator: None,
-            video_channel: Box::new(video_iter),
+            video_channel: buffers,
             finished_buffers: LinkedList::new()
-        };
-
-        processor.next_video_buffer();
+        }
+    }
 
-        processor
+    /// Blank canvas buffers, one frame each, for painting visuals without a video stream.
+    pub fn canvas(format: VideoFormat) -> VideoIter {
+        Self::from_buffers(Box::new((0u64..).map(move |index| VideoBuffer::canvas(format, index))))
     }
 
     pub fn format(&mut self) -> Option<VideoFormat> {
@@ -42,6 +49,14 @@ impl<'i> VideoIter {
         self.finished_buffers.pop_back()
     }
 
+    /// Moves the buffer being iterated into the finished buffers, without pulling another one.
+    pub fn finish(&mut self) {
+        let last_iter = ::std::mem::replace(&mut self.video_frame_iterator, None);
+        if let Some(iter) = last_iter {
+            self.finished_buffers.push_front(iter.into_buffer());
+        }
+    }
+
     fn next_video_buffer(&mut self) {
         let last_iter = ::std::mem::replace(&mut self.video_frame_iterator, None);
         match last_iter {
@@ -0,0 +1,69 @@
+use recode::pipeline::encoder_settings::*;
+use recode::pipeline::output_format::*;
+
+#[test]
+pub fn test_container_from_path() {
+    assert_eq!(Container::Mp4, Container::from_path("out.mp4").unwrap());
+    assert_eq!(Container::Mkv, Container::from_path("out.MKV").unwrap());
+    assert_eq!(Container::WebM, Container::from_path("dir/out.webm").unwrap());
+}
+
+#[test]
+pub fn test_container_from_unknown_path() {
+    for path in &["out", "out.mvk", "dir.mkv/out"] {
+        let e = Container::from_path(path).unwrap_err();
+        assert_eq!(Some(path.to_string()), e.downcast_ref::<UnknownContainer>().map(|e| e.0.clone()));
+    }
+}
+
+#[test]
+pub fn test_default_codecs_are_supported() {
+    for container in &[Container::Mp4, Container::Mkv, Container::WebM] {
+        let (video, audio) = container.default_codecs();
+        assert!(container.check(video, audio).is_ok());
+    }
+
+    assert!(Container::Mp4.check(VideoCodec::VP9, AudioCodec::Aac).is_err());
+    assert!(Container::WebM.check(VideoCodec::VP9, AudioCodec::Aac).is_err());
+}
+
+#[test]
+pub fn test_image_format_from_path() {
+    assert_eq!(ImageFormat::Png, ImageFormat::from_path("frames/%05d.png").unwrap());
+    assert_eq!(ImageFormat::Jpeg, ImageFormat::from_path("frames/%05d.jpeg").unwrap());
+    assert!(ImageFormat::from_path("frames/%05d.gif").is_err());
+}
+
+#[test]
+pub fn test_two_pass_needs_abr() {
+    let settings = EncoderSettings::default();
+    let first = settings.for_pass(EncodePass::First, "out.mp4.pass-stats").unwrap();
+    assert_eq!(EncodePass::First, first.pass);
+    assert_eq!(Some("out.mp4.pass-stats".to_string()), first.stats_file);
+
+    let crf = EncoderSettings {
+        rate_control: Some(RateControl::Crf),
+        ..EncoderSettings::default()
+    };
+    assert!(crf.for_pass(EncodePass::First, "out.mp4.pass-stats").is_err());
+}
+
+#[test]
+pub fn test_rejects_unknown_x264_names() {
+    let valid = EncoderSettings {
+        speed_preset: Some("veryfast".to_string()),
+        tune: Some("film+fastdecode+zerolatency".to_string()),
+        profile: Some("main".to_string()),
+        ..EncoderSettings::default()
+    };
+    assert!(valid.validate().is_ok());
+
+    let preset = EncoderSettings { speed_preset: Some("quick".to_string()), ..valid.clone() };
+    assert!(preset.validate().unwrap_err().downcast_ref::<UnknownSpeedPreset>().is_some());
+
+    let tune = EncoderSettings { tune: Some("film+grain".to_string()), ..valid.clone() };
+    assert!(tune.validate().unwrap_err().downcast_ref::<UnknownTune>().is_some());
+
+    let profile = EncoderSettings { profile: Some("high-10".to_string()), ..valid.clone() };
+    assert!(profile.validate().unwrap_err().downcast_ref::<UnknownProfile>().is_some());
+}
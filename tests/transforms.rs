@@ -0,0 +1,74 @@
+use recode::*;
+use recode::pipeline::measures::*;
+
+fn audio_buffer(samples: Vec<f32>) -> AudioBuffer {
+    gstreamer::init().unwrap();
+    AudioBuffer::new(gstreamer::Buffer::new(), samples, AudioFormat::new(48000, 2))
+}
+
+fn video_buffer() -> VideoBuffer {
+    VideoBuffer::canvas(VideoFormat::new(gstreamer::Fraction::new(25, 1), 64, 36), 0)
+}
+
+/// Feeds one block of audio and one video frame through the transform, returning the frame data.
+fn run(transform: &mut dyn FrameTransform, samples: Vec<f32>) -> Vec<u8> {
+    let mut audio = audio_buffer(samples).into_iter();
+    while let Some(mut block) = audio.next_block() {
+        transform.process_audio_block(&mut block);
+    }
+
+    let mut video = video_buffer().into_iter();
+    let mut frame = video.next().unwrap();
+    let time = frame.time;
+    transform.process_video_frame(&mut frame, time);
+    frame.data.to_vec()
+}
+
+fn noise(frames: usize) -> Vec<f32> {
+    (0..frames * 2).map(|i| if i % 4 < 2 { 0.5f32 } else { -0.5f32 }).collect()
+}
+
+#[test]
+pub fn test_builtin_transforms() {
+    let registry = TransformRegistry::builtin();
+    let params = TransformParams::default();
+
+    for entry in registry.entries() {
+        let mut transform = registry.create(entry.name, &params).unwrap();
+        let data = run(&mut *transform, noise(4800));
+        assert_eq!(video_buffer().buffer.len(), data.len());
+    }
+
+    assert!(registry.create("hue+identity", &params).is_ok());
+    assert!(registry.create("hue+missing", &params).is_err());
+}
+
+#[test]
+pub fn test_identity_leaves_frames() {
+    let registry = TransformRegistry::builtin();
+    let mut transform = registry.create("identity", &TransformParams::default()).unwrap();
+    assert_eq!(video_buffer().buffer, run(&mut *transform, noise(4800)));
+}
+
+#[test]
+pub fn test_volume_measure() {
+    let mut quiet = audio_buffer(vec![0.01f32; 9600]).into_iter();
+    let mut loud = audio_buffer(noise(4800)).into_iter();
+
+    let mut measure = NormalizedAudioVolumeMeasure::new(&quiet.format());
+    measure.update_block(&quiet.next_block().unwrap());
+    let before = measure.value(());
+    measure.update_block(&loud.next_block().unwrap());
+    assert!(measure.value(()) > before);
+}
+
+#[test]
+pub fn test_time_range() {
+    let range = TimeRange { start: 1f64, end: Some(2f64) };
+    assert!(!range.is_all());
+    assert!(!range.contains(0.5f64, 0.5f64));
+    assert!(range.contains(0.75f64, 0.5f64));
+    assert!(range.contains(1.5f64, 0.04f64));
+    assert!(!range.contains(2f64, 0.04f64));
+    assert!(TimeRange::all().is_all());
+}